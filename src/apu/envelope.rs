@@ -0,0 +1,62 @@
+//! Volume envelope of the pulse and noise channels, `NRx2`. Clocked at 64 Hz by the frame
+//! sequencer.
+
+#[derive(Debug, Clone, Default)]
+pub struct Envelope {
+    /// `NRx2`: initial volume (7-4), increase (3), period (2-0).
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.register = val;
+    }
+
+    /// The DAC is powered while initial volume or direction are set, a channel with its DAC
+    /// off is silent and can't be turned on.
+    pub fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    /// Current volume, 0-15.
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Reload volume and timer on a channel trigger.
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    pub fn clock(&mut self) {
+        let period = self.register & 0b111;
+        if period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period();
+        if self.register & 0b1000 != 0 {
+            self.volume = (self.volume + 1).min(15);
+        } else {
+            self.volume = self.volume.saturating_sub(1);
+        }
+    }
+
+    /// Clocks between volume changes, a period of 0 is treated as 8.
+    fn period(&self) -> u8 {
+        match self.register & 0b111 {
+            0 => 8,
+            period => period,
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! High-pass filter mimicking the capacitor in the DMG's output stage. It removes the DC
+//! offset of the channel DACs, so silence settles at 0 instead of clicking on and off.
+
+/// Charge the capacitor keeps per dot.
+const CHARGE_PER_DOT: f64 = 0.999_958;
+
+#[derive(Debug, Clone)]
+pub struct HighPass {
+    /// Charge kept between two samples.
+    factor: f32,
+    charge: f32,
+}
+
+impl HighPass {
+    /// Filter running at one sample every `dots_per_sample` dots.
+    pub fn new(dots_per_sample: f64) -> Self {
+        let mut filter = Self {
+            factor: 0.0,
+            charge: 0.0,
+        };
+        filter.set_rate(dots_per_sample);
+        filter
+    }
+
+    /// Change the sample rate to one sample every `dots_per_sample` dots.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn set_rate(&mut self, dots_per_sample: f64) {
+        self.factor = CHARGE_PER_DOT.powf(dots_per_sample) as f32;
+    }
+
+    /// Filter `input`. While all DACs are off the output is silent and the capacitor holds
+    /// its charge.
+    pub fn apply(&mut self, input: f32, dacs_enabled: bool) -> f32 {
+        if !dacs_enabled {
+            return 0.0;
+        }
+        let output = input - self.charge;
+        self.charge = input - output * self.factor;
+        output
+    }
+}
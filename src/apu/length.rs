@@ -0,0 +1,64 @@
+//! Length counter, turns a channel off after a programmed time. Clocked at 256 Hz by the
+//! frame sequencer.
+
+#[derive(Debug, Clone)]
+pub struct Length {
+    /// Length loaded on trigger if the counter ran out, 64 or 256 for the wave channel.
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    pub fn new(max: u16) -> Self {
+        Self {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    /// Load the length register, the counter runs `max - val` clocks.
+    pub fn load(&mut self, val: u8) {
+        self.counter = self.max - u16::from(val);
+    }
+
+    /// Whether the counter is enabled, bit 6 of `NRx4`.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Clear the enable bit, the counter itself survives the APU powering off.
+    pub fn power_off(&mut self) {
+        self.enabled = false;
+    }
+
+    /// Apply a write to `NRx4`. `extra_clock` is set if the next frame sequencer step doesn't
+    /// clock the length, then enabling the counter clocks it once more. Returns whether the
+    /// channel runs out, which only counts if it isn't triggered by the same write.
+    pub fn write(&mut self, enable: bool, trigger: bool, extra_clock: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enable;
+        let mut expired = false;
+        if !was_enabled && enable && extra_clock && self.counter != 0 {
+            self.counter -= 1;
+            expired = self.counter == 0;
+        }
+        if trigger && self.counter == 0 {
+            self.counter = self.max;
+            if enable && extra_clock {
+                self.counter -= 1;
+            }
+        }
+        expired && !trigger
+    }
+
+    /// Clock the counter, returns whether the channel runs out.
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
@@ -1,11 +1,468 @@
 //! Not the guy from Kwik-E-Mart.
+//!
+//! The audio processing unit. Four channels, two pulse, wave and noise, produce digital
+//! values from 0 to 15. Their length counters, envelopes and channel 1's sweep are clocked
+//! by the 512 Hz frame sequencer. The mixer pans and scales them by `NR51` and `NR50` and
+//! resamples the result to [`SAMPLE_RATE`].
+
+mod envelope;
+mod filter;
+mod length;
+mod noise;
+mod pulse;
+mod wave;
+
+use filter::HighPass;
+use noise::Noise;
+use pulse::Pulse;
+use wave::Wave;
+
+/// First register of pulse channel 1, `NR10`.
+const REGISTER_NR10: u16 = 0xFF10;
+/// First register of pulse channel 2, the unused `NR20`.
+const REGISTER_NR20: u16 = 0xFF15;
+/// Last register of pulse channel 2, `NR24`.
+const REGISTER_NR24: u16 = 0xFF19;
+/// First register of the wave channel, `NR30`.
+const REGISTER_NR30: u16 = 0xFF1A;
+/// Last register of the wave channel, `NR34`.
+const REGISTER_NR34: u16 = 0xFF1E;
+/// First register of the noise channel, the unused `NR40`.
+const REGISTER_NR40: u16 = 0xFF1F;
+/// Last register of the noise channel, `NR44`.
+const REGISTER_NR44: u16 = 0xFF23;
+/// Master volume, `NR50`.
+const REGISTER_NR50: u16 = 0xFF24;
+/// Panning, `NR51`.
+const REGISTER_NR51: u16 = 0xFF25;
+/// Power and channel status, `NR52`.
+const REGISTER_NR52: u16 = 0xFF26;
+/// Start of wave RAM.
+const WAVE_RAM_OFFSET: u16 = 0xFF30;
+/// End of wave RAM.
+const WAVE_RAM_END: u16 = 0xFF3F;
+
+/// Length registers, writable while the APU is off.
+const REGISTER_NR11: u16 = 0xFF11;
+const REGISTER_NR21: u16 = 0xFF16;
+const REGISTER_NR31: u16 = 0xFF1B;
+const REGISTER_NR41: u16 = 0xFF20;
+
+/// DIV bit whose falling edge clocks the frame sequencer, at 512 Hz.
+const DIV_FRAME_SEQUENCER_BIT: u8 = 0x10;
+
+/// Output sample rate in Hz.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Machine cycles per second.
+const CYCLES_PER_SECOND: f64 = 1_048_576.0;
 
 #[derive(Debug, Clone)]
-pub struct Apu {}
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    wave: Wave,
+    noise: Noise,
+    /// Power, bit 7 of `NR52`. Registers are cleared and read-only while off.
+    powered: bool,
+    /// Master volume, `NR50`: VIN left (7), left volume (6-4), VIN right (3), right volume
+    /// (2-0). VIN, cartridge audio, is not emulated.
+    nr50: u8,
+    /// Panning, `NR51`: channels 4-1 on the left (7-4) and on the right (3-0).
+    nr51: u8,
+    /// Frame sequencer bit of DIV on the last cycle.
+    div_bit: bool,
+    /// Next frame sequencer step, 0-7.
+    frame_step: u8,
+    /// Machine cycles per output sample.
+    cycles_per_sample: f64,
+    /// Machine cycles since the last output sample.
+    sample_cycles: f64,
+    /// Mixed output since the last sample, left and right, averaged into the next one.
+    accumulator: [f32; 2],
+    accumulated: u32,
+    high_pass: [HighPass; 2],
+    /// Produced samples, interleaved stereo.
+    samples: Vec<f32>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Apu {
     pub fn new() -> Self {
-        tracing::warn!("apu not yet implemented");
-        Self {}
+        let mut apu = Self {
+            pulse1: Pulse::with_sweep(),
+            pulse2: Pulse::new(),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            powered: true,
+            nr50: 0,
+            nr51: 0,
+            div_bit: false,
+            frame_step: 0,
+            cycles_per_sample: 0.0,
+            sample_cycles: 0.0,
+            accumulator: [0.0; 2],
+            accumulated: 0,
+            high_pass: [HighPass::new(0.0), HighPass::new(0.0)],
+            samples: Vec::new(),
+        };
+        apu.set_sample_rate(f64::from(SAMPLE_RATE));
+        apu
+    }
+
+    /// State the boot ROM leaves behind: channel 1 still enabled after the boot sound faded
+    /// out.
+    pub fn post_boot(&mut self) {
+        self.pulse1.post_boot();
+    }
+
+    /// Resample the output to `rate` Hz. Frontends nudge it around [`SAMPLE_RATE`] to keep
+    /// their audio buffer from running dry or overflowing.
+    pub fn set_sample_rate(&mut self, rate: f64) {
+        self.cycles_per_sample = CYCLES_PER_SECOND / rate;
+        for high_pass in &mut self.high_pass {
+            high_pass.set_rate(self.cycles_per_sample * 4.0);
+        }
+    }
+
+    /// Takes the samples produced since the last call.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Digital output of the four channels, 0-15 each.
+    pub fn channel_output(&self) -> [u8; 4] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ]
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            REGISTER_NR10..REGISTER_NR20 => self.pulse1.read_register(address - REGISTER_NR10),
+            REGISTER_NR20..=REGISTER_NR24 => self.pulse2.read_register(address - REGISTER_NR20),
+            REGISTER_NR30..=REGISTER_NR34 => self.wave.read_register(address - REGISTER_NR30),
+            REGISTER_NR40..=REGISTER_NR44 => self.noise.read_register(address - REGISTER_NR40),
+            REGISTER_NR50 => self.nr50,
+            REGISTER_NR51 => self.nr51,
+            REGISTER_NR52 => self.nr52(),
+            WAVE_RAM_OFFSET..=WAVE_RAM_END => {
+                self.wave.read_ram(usize::from(address - WAVE_RAM_OFFSET))
+            }
+            // Unused
+            0xFF27..WAVE_RAM_OFFSET => 0xFF,
+            _ => panic!("invalid apu register {address:#06X}"),
+        }
+    }
+
+    /// `NR52`: power (7) and which channels are playing (3-0).
+    fn nr52(&self) -> u8 {
+        0x70 | u8::from(self.powered) << 7
+            | u8::from(self.noise.enabled()) << 3
+            | u8::from(self.wave.enabled()) << 2
+            | u8::from(self.pulse2.enabled()) << 1
+            | u8::from(self.pulse1.enabled())
+    }
+
+    /// Writes a sound register or wave RAM. While powered off only `NR52`, wave RAM and the
+    /// length counters (the DMG keeps those) can be written.
+    pub fn write_register(&mut self, address: u16, val: u8) {
+        if self.powered {
+            self.write_powered(address, val);
+            return;
+        }
+        match address {
+            REGISTER_NR52 | REGISTER_NR31 | WAVE_RAM_OFFSET..=WAVE_RAM_END => {
+                self.write_powered(address, val);
+            }
+            // The duty bits of NR11 and NR21 stay cleared.
+            REGISTER_NR11 | REGISTER_NR21 | REGISTER_NR41 => {
+                self.write_powered(address, val & 0x3F);
+            }
+            _ => (),
+        }
+    }
+
+    fn write_powered(&mut self, address: u16, val: u8) {
+        // Enabling a length counter clocks it once if the next step won't.
+        let extra_length_clock = !self.frame_step.is_multiple_of(2);
+        match address {
+            REGISTER_NR10..REGISTER_NR20 => {
+                self.pulse1
+                    .write_register(address - REGISTER_NR10, val, extra_length_clock);
+            }
+            REGISTER_NR20..=REGISTER_NR24 => {
+                self.pulse2
+                    .write_register(address - REGISTER_NR20, val, extra_length_clock);
+            }
+            REGISTER_NR30..=REGISTER_NR34 => {
+                self.wave
+                    .write_register(address - REGISTER_NR30, val, extra_length_clock);
+            }
+            REGISTER_NR40..=REGISTER_NR44 => {
+                self.noise
+                    .write_register(address - REGISTER_NR40, val, extra_length_clock);
+            }
+            REGISTER_NR50 => self.nr50 = val,
+            REGISTER_NR51 => self.nr51 = val,
+            REGISTER_NR52 => self.set_power(val & 0x80 != 0),
+            WAVE_RAM_OFFSET..=WAVE_RAM_END => {
+                self.wave
+                    .write_ram(usize::from(address - WAVE_RAM_OFFSET), val);
+            }
+            // Unused
+            0xFF27..WAVE_RAM_OFFSET => (),
+            _ => panic!("invalid apu register {address:#06X}"),
+        }
+    }
+
+    /// Power the APU on or off. Powering off clears all registers, powering on restarts the
+    /// frame sequencer.
+    fn set_power(&mut self, on: bool) {
+        if on && !self.powered {
+            self.frame_step = 0;
+        } else if !on && self.powered {
+            self.pulse1.power_off();
+            self.pulse2.power_off();
+            self.wave.power_off();
+            self.noise.power_off();
+            self.nr50 = 0;
+            self.nr51 = 0;
+        }
+        self.powered = on;
+    }
+
+    /// Advance by one machine cycle. `div` is the DIV register, the frame sequencer steps
+    /// whenever its bit 4 falls.
+    pub fn tick(&mut self, div: u8) {
+        let div_bit = div & DIV_FRAME_SEQUENCER_BIT != 0;
+        if self.powered {
+            self.pulse1.tick();
+            self.pulse2.tick();
+            self.wave.tick();
+            self.noise.tick();
+            if self.div_bit && !div_bit {
+                self.step_frame_sequencer();
+            }
+        }
+        self.div_bit = div_bit;
+
+        self.resample();
+    }
+
+    /// Average the mixed output into samples at the output rate.
+    #[allow(clippy::cast_precision_loss)]
+    fn resample(&mut self) {
+        let [left, right] = self.mix();
+        self.accumulator[0] += left;
+        self.accumulator[1] += right;
+        self.accumulated += 1;
+        self.sample_cycles += 1.0;
+        if self.sample_cycles < self.cycles_per_sample {
+            return;
+        }
+        self.sample_cycles -= self.cycles_per_sample;
+
+        let dacs_enabled = self.powered && self.dacs_enabled();
+        let count = self.accumulated as f32;
+        for (sample, high_pass) in self.accumulator.iter_mut().zip(&mut self.high_pass) {
+            self.samples
+                .push(high_pass.apply(*sample / count, dacs_enabled));
+            *sample = 0.0;
+        }
+        self.accumulated = 0;
+    }
+
+    fn dacs_enabled(&self) -> bool {
+        self.pulse1.dac_enabled()
+            || self.pulse2.dac_enabled()
+            || self.wave.dac_enabled()
+            || self.noise.dac_enabled()
+    }
+
+    /// Left and right output, -1.0 to 1.0. Each channel's DAC maps its digital output 0-15
+    /// linearly to 1.0 to -1.0, a disabled DAC outputs 0.
+    fn mix(&self) -> [f32; 2] {
+        if !self.powered {
+            return [0.0; 2];
+        }
+        let dacs = [
+            self.pulse1.dac_enabled(),
+            self.pulse2.dac_enabled(),
+            self.wave.dac_enabled(),
+            self.noise.dac_enabled(),
+        ];
+        let mut mixed = [0.0; 2];
+        for (channel, (output, dac)) in self.channel_output().into_iter().zip(dacs).enumerate() {
+            if !dac {
+                continue;
+            }
+            let analog = 1.0 - f32::from(output) / 7.5;
+            if self.nr51 & (0x10 << channel) != 0 {
+                mixed[0] += analog;
+            }
+            if self.nr51 & (0x01 << channel) != 0 {
+                mixed[1] += analog;
+            }
+        }
+        let volume = [(self.nr50 >> 4) & 0b111, self.nr50 & 0b111];
+        for (output, volume) in mixed.iter_mut().zip(volume) {
+            *output *= f32::from(volume + 1) / 8.0 / 4.0;
+        }
+        mixed
+    }
+
+    /// Clock length counters at 256 Hz, the sweep at 128 Hz and envelopes at 64 Hz.
+    fn step_frame_sequencer(&mut self) {
+        if self.frame_step.is_multiple_of(2) {
+            self.pulse1.clock_length();
+            self.pulse2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step % 4 == 2 {
+            self.pulse1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.pulse1.clock_envelope();
+            self.pulse2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Apu;
+
+    /// Step the frame sequencer once by letting DIV bit 4 fall.
+    fn step_frame_sequencer(apu: &mut Apu) {
+        apu.tick(0x10);
+        apu.tick(0x00);
+    }
+
+    #[test]
+    fn register_read_masks() {
+        let mut apu = Apu::new();
+        for address in 0xFF10..=0xFF19 {
+            apu.write_register(address, 0x00);
+        }
+        let read: Vec<u8> = (0xFF10..=0xFF19)
+            .map(|address| apu.read_register(address))
+            .collect();
+        assert_eq!(
+            read,
+            [0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF]
+        );
+        apu.write_register(0xFF11, 0xFF);
+        apu.write_register(0xFF12, 0x5A);
+        apu.write_register(0xFF14, 0x40);
+        assert_eq!(apu.read_register(0xFF11), 0xFF);
+        assert_eq!(apu.read_register(0xFF12), 0x5A);
+        assert_eq!(apu.read_register(0xFF14), 0xFF);
+    }
+
+    #[test]
+    fn frame_sequencer_clocks_length() {
+        let mut apu = Apu::new();
+        apu.write_register(0xFF17, 0xF0);
+        // Length 1, runs out on the first length clock.
+        apu.write_register(0xFF16, 63);
+        apu.write_register(0xFF19, 0xC7);
+        for _ in 0..100 {
+            apu.tick(0x00);
+        }
+        assert!(apu.pulse2.enabled());
+        step_frame_sequencer(&mut apu);
+        assert!(!apu.pulse2.enabled());
+    }
+
+    #[test]
+    fn power_off_clears_registers() {
+        let mut apu = Apu::new();
+        apu.write_register(0xFF24, 0x77);
+        apu.write_register(0xFF25, 0xFF);
+        apu.write_register(0xFF12, 0xF0);
+        apu.write_register(0xFF14, 0x80);
+        apu.write_register(0xFF30, 0x42);
+        assert_eq!(apu.read_register(0xFF26), 0xF1);
+
+        apu.write_register(0xFF26, 0x00);
+        assert_eq!(apu.read_register(0xFF26), 0x70);
+        assert_eq!(apu.read_register(0xFF24), 0x00);
+        assert_eq!(apu.read_register(0xFF25), 0x00);
+        assert_eq!(apu.read_register(0xFF12), 0x00);
+        assert_eq!(apu.read_register(0xFF30), 0x42);
+
+        // Writes are ignored while off, except lengths and wave RAM.
+        apu.write_register(0xFF12, 0xF0);
+        assert_eq!(apu.read_register(0xFF12), 0x00);
+        apu.write_register(0xFF11, 0xFF);
+        assert_eq!(apu.read_register(0xFF11), 0x3F);
+
+        apu.write_register(0xFF26, 0x80);
+        apu.write_register(0xFF12, 0xF0);
+        assert_eq!(apu.read_register(0xFF12), 0xF0);
+    }
+
+    #[test]
+    fn mixer_pans_channels() {
+        let mut apu = Apu::new();
+        apu.write_register(0xFF24, 0x77);
+        // Channel 2 left only, constant output through duty 75%.
+        apu.write_register(0xFF25, 0x20);
+        apu.write_register(0xFF16, 0xC0);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF19, 0x80);
+        let [left, right] = apu.mix();
+        assert!(left != 0.0);
+        assert!(right == 0.0);
+
+        apu.write_register(0xFF24, 0x07);
+        let [quiet, _] = apu.mix();
+        assert!((quiet - left / 8.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn resamples_to_output_rate() {
+        let mut apu = Apu::new();
+        // One second of machine cycles.
+        for _ in 0..1_048_576 {
+            apu.tick(0x00);
+        }
+        assert_eq!(apu.take_samples().len(), 2 * 48_000);
+    }
+
+    #[test]
+    fn wave_and_noise_registers() {
+        let mut apu = Apu::new();
+        for address in 0xFF1A..=0xFF1E {
+            apu.write_register(address, 0x00);
+        }
+        let read: Vec<u8> = (0xFF1A..=0xFF1E)
+            .map(|address| apu.read_register(address))
+            .collect();
+        assert_eq!(read, [0x7F, 0xFF, 0x9F, 0xFF, 0xBF]);
+        for address in 0xFF1F..=0xFF23 {
+            apu.write_register(address, 0x00);
+        }
+        let read: Vec<u8> = (0xFF1F..=0xFF23)
+            .map(|address| apu.read_register(address))
+            .collect();
+        assert_eq!(read, [0xFF, 0xFF, 0x00, 0x00, 0xBF]);
+
+        apu.write_register(0xFF30, 0x12);
+        apu.write_register(0xFF3F, 0xEF);
+        assert_eq!(apu.read_register(0xFF30), 0x12);
+        assert_eq!(apu.read_register(0xFF3F), 0xEF);
     }
 }
@@ -0,0 +1,222 @@
+//! Noise channel 4 (`NR41`-`NR44`). Outputs the low bit of a linear feedback shift register,
+//! 15 bits wide or 7 bits for a more periodic tone, with volume envelope and length counter.
+
+use super::{envelope::Envelope, length::Length};
+
+/// Bits of `NR40`-`NR44` that always read as 1, there is no `NR40`.
+const READ_MASKS: [u8; 5] = [0xFF, 0xFF, 0x00, 0x00, 0xBF];
+
+/// Dots per LFSR clock for the divisor codes in `NR43`, before the clock shift.
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Clock shifts of 14 and 15 stop the LFSR.
+const CLOCK_SHIFT_MAX: u8 = 13;
+
+#[derive(Debug, Clone)]
+pub struct Noise {
+    enabled: bool,
+    /// `NR43`: clock shift (7-4), 7 bit width (3), divisor code (2-0).
+    polynomial: u8,
+    lfsr: u16,
+    /// Dots until the next LFSR clock.
+    timer: u32,
+    length: Length,
+    envelope: Envelope,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            polynomial: 0,
+            lfsr: 0,
+            timer: 0,
+            length: Length::new(64),
+            envelope: Envelope::default(),
+        }
+    }
+
+    /// Reset all registers as the APU powers off, the length counter is kept.
+    pub fn power_off(&mut self) {
+        let mut length = self.length.clone();
+        length.power_off();
+        *self = Self {
+            length,
+            ..Self::new()
+        };
+    }
+
+    /// Whether the channel is playing, as shown in `NR52`.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Digital output, 0-15. The inverted low bit of the LFSR.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 1 != 0 {
+            return 0;
+        }
+        self.envelope.volume()
+    }
+
+    /// Reads `NR40`-`NR44`, selected by `index`.
+    pub fn read_register(&self, index: u16) -> u8 {
+        let val = match index {
+            2 => self.envelope.read(),
+            3 => self.polynomial,
+            4 => u8::from(self.length.enabled()) << 6,
+            _ => 0,
+        };
+        val | READ_MASKS[index as usize]
+    }
+
+    /// Writes `NR40`-`NR44`, selected by `index`. `extra_length_clock` is set if the next
+    /// frame sequencer step doesn't clock the length counter.
+    pub fn write_register(&mut self, index: u16, val: u8, extra_length_clock: bool) {
+        match index {
+            1 => self.length.load(val & 0x3F),
+            2 => {
+                self.envelope.write(val);
+                self.enabled &= self.dac_enabled();
+            }
+            3 => self.polynomial = val,
+            4 => {
+                let trigger = val & 0x80 != 0;
+                if self
+                    .length
+                    .write(val & 0x40 != 0, trigger, extra_length_clock)
+                {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.lfsr = 0x7FFF;
+        self.timer = self.period();
+        self.envelope.trigger();
+    }
+
+    /// Dots per LFSR clock.
+    fn period(&self) -> u32 {
+        DIVISORS[usize::from(self.polynomial & 0b111)] << (self.polynomial >> 4)
+    }
+
+    /// Advance the frequency timer by one machine cycle.
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(4);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period();
+        if self.polynomial >> 4 > CLOCK_SHIFT_MAX {
+            return;
+        }
+        let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | feedback << 14;
+        if self.polynomial & 0b1000 != 0 {
+            self.lfsr = self.lfsr & !(1 << 6) | feedback << 6;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Noise;
+
+    /// Noise channel at full volume, triggered with `polynomial` in `NR43`.
+    fn triggered(polynomial: u8) -> Noise {
+        let mut noise = Noise::new();
+        noise.write_register(2, 0xF0, false);
+        noise.write_register(3, polynomial, false);
+        noise.write_register(4, 0x80, false);
+        noise
+    }
+
+    /// LFSR states of the next `count` clocks, the channel clocks it every 2 machine cycles.
+    fn lfsr_states(noise: &mut Noise, count: usize) -> Vec<u16> {
+        (0..count)
+            .map(|_| {
+                noise.tick();
+                noise.tick();
+                noise.lfsr
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lfsr_15_bit() {
+        let mut noise = triggered(0x00);
+        assert_eq!(noise.output(), 0);
+        assert_eq!(lfsr_states(&mut noise, 3), [0x3FFF, 0x1FFF, 0x0FFF]);
+        // Shifted ones stay silent until a zero reaches bit 0.
+        let states = lfsr_states(&mut noise, 11);
+        assert_eq!(states[10], 0x0001);
+        assert_eq!(noise.output(), 0);
+        assert_eq!(lfsr_states(&mut noise, 1), [0x4000]);
+        assert_eq!(noise.output(), 15);
+    }
+
+    #[test]
+    fn lfsr_7_bit_repeats() {
+        let mut noise = triggered(0x08);
+        let states = lfsr_states(&mut noise, 254);
+        let bits: Vec<u16> = states.iter().map(|lfsr| lfsr & 0x7F).collect();
+        assert_eq!(bits[..127], bits[127..]);
+    }
+
+    #[test]
+    fn clock_shift_stops_lfsr() {
+        let mut noise = triggered(0xE0);
+        for _ in 0..0x10000 {
+            noise.tick();
+        }
+        assert_eq!(noise.lfsr, 0x7FFF);
+    }
+
+    #[test]
+    fn period_from_divisor_and_shift() {
+        assert_eq!(triggered(0x00).period(), 8);
+        assert_eq!(triggered(0x03).period(), 48);
+        assert_eq!(triggered(0x25).period(), 320);
+    }
+
+    #[test]
+    fn length_and_dac() {
+        let mut noise = triggered(0x00);
+        noise.write_register(1, 0x3F, false);
+        noise.write_register(4, 0xC0, false);
+        noise.clock_length();
+        assert!(!noise.enabled());
+
+        let mut noise = triggered(0x00);
+        noise.write_register(2, 0x00, false);
+        assert!(!noise.enabled());
+    }
+}
@@ -0,0 +1,356 @@
+//! Pulse channels 1 (`NR10`-`NR14`) and 2 (`NR21`-`NR24`). A square wave with four duty
+//! cycles, volume envelope and length counter. Channel 1 adds a frequency sweep.
+
+use super::{envelope::Envelope, length::Length};
+
+/// Waveforms of the four duty cycles, 12.5%, 25%, 50% and 75%, first step in the high bit.
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// Bits of `NRx0`-`NRx4` that always read as 1.
+const READ_MASKS: [u8; 5] = [0x80, 0x3F, 0x00, 0xFF, 0xBF];
+
+/// Highest frequency value, the sweep turns the channel off above it.
+const FREQUENCY_MAX: u16 = 0x7FF;
+
+/// Frequency sweep of channel 1, `NR10`. Clocked at 128 Hz by the frame sequencer.
+#[derive(Debug, Clone, Default)]
+pub struct Sweep {
+    /// `NR10`: period (6-4), decrease (3), shift (2-0).
+    register: u8,
+    timer: u8,
+    enabled: bool,
+    /// Frequency the sweep calculates with, copied on trigger.
+    shadow: u16,
+    /// A decreasing calculation was made since the trigger. Switching to increase afterwards
+    /// turns the channel off.
+    negated: bool,
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0b111
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0b111
+    }
+
+    fn decrease(&self) -> bool {
+        self.register & 0b1000 != 0
+    }
+
+    /// Reload the timer, a period of 0 is treated as 8.
+    fn reload(&mut self) {
+        self.timer = match self.period() {
+            0 => 8,
+            period => period,
+        };
+    }
+
+    /// Next frequency, `None` if it overflows.
+    fn calculate(&mut self) -> Option<u16> {
+        let delta = self.shadow >> self.shift();
+        let frequency = if self.decrease() {
+            self.negated = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+        (frequency <= FREQUENCY_MAX).then_some(frequency)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pulse {
+    enabled: bool,
+    /// Selected duty cycle, bits 7-6 of `NRx1`.
+    duty: u8,
+    /// Position in the waveform, 0-7.
+    duty_step: u8,
+    /// 11 bit frequency value from `NRx3` and `NRx4`.
+    frequency: u16,
+    /// Dots until the next waveform step.
+    timer: u16,
+    length: Length,
+    envelope: Envelope,
+    /// Only channel 1 has a sweep.
+    sweep: Option<Sweep>,
+}
+
+impl Pulse {
+    /// Channel 1, with frequency sweep.
+    pub fn with_sweep() -> Self {
+        Self {
+            sweep: Some(Sweep::default()),
+            ..Self::new()
+        }
+    }
+
+    /// Channel 2.
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: Length::new(64),
+            envelope: Envelope::default(),
+            sweep: None,
+        }
+    }
+
+    /// Reset all registers as the APU powers off, the length counter is kept.
+    pub fn power_off(&mut self) {
+        let mut length = self.length.clone();
+        length.power_off();
+        *self = if self.sweep.is_some() {
+            Self::with_sweep()
+        } else {
+            Self::new()
+        };
+        self.length = length;
+    }
+
+    /// Turn the channel on without a trigger, at volume 0 as the boot sound left it.
+    pub fn post_boot(&mut self) {
+        self.enabled = self.dac_enabled();
+    }
+
+    /// Whether the channel is playing, as shown in `NR52`.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Digital output, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let high = (DUTY_PATTERNS[usize::from(self.duty)] >> (7 - self.duty_step)) & 1;
+        high * self.envelope.volume()
+    }
+
+    /// Reads `NRx0`-`NRx4`, selected by `index`. Channel 2 has no `NR20`, it reads 0xFF.
+    pub fn read_register(&self, index: u16) -> u8 {
+        let val = match index {
+            0 => self.sweep.as_ref().map_or(0xFF, |sweep| sweep.register),
+            1 => self.duty << 6,
+            2 => self.envelope.read(),
+            4 => u8::from(self.length.enabled()) << 6,
+            _ => 0,
+        };
+        val | READ_MASKS[index as usize]
+    }
+
+    /// Writes `NRx0`-`NRx4`, selected by `index`. `extra_length_clock` is set if the next
+    /// frame sequencer step doesn't clock the length counter.
+    pub fn write_register(&mut self, index: u16, val: u8, extra_length_clock: bool) {
+        match index {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.register = val;
+                    if sweep.negated && !sweep.decrease() {
+                        self.enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = val >> 6;
+                self.length.load(val & 0x3F);
+            }
+            2 => {
+                self.envelope.write(val);
+                self.enabled &= self.dac_enabled();
+            }
+            3 => self.frequency = self.frequency & 0x700 | u16::from(val),
+            4 => {
+                self.frequency = self.frequency & 0xFF | u16::from(val & 0b111) << 8;
+                let trigger = val & 0x80 != 0;
+                if self
+                    .length
+                    .write(val & 0x40 != 0, trigger, extra_length_clock)
+                {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow = self.frequency;
+            sweep.negated = false;
+            sweep.reload();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+            if sweep.shift() != 0 && sweep.calculate().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Dots per waveform step.
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    /// Advance the frequency timer by one machine cycle.
+    pub fn tick(&mut self) {
+        self.timer = self.timer.saturating_sub(4);
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.reload();
+        if !sweep.enabled || sweep.period() == 0 {
+            return;
+        }
+        match sweep.calculate() {
+            Some(frequency) if sweep.shift() != 0 => {
+                sweep.shadow = frequency;
+                self.frequency = frequency;
+                // The new frequency is checked for overflow right away.
+                if sweep.calculate().is_none() {
+                    self.enabled = false;
+                }
+            }
+            Some(_) => (),
+            None => self.enabled = false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pulse;
+
+    /// Trigger a channel at full volume with the given duty cycle and frequency.
+    fn triggered(duty: u8, frequency: u16) -> Pulse {
+        let mut pulse = Pulse::new();
+        pulse.write_register(1, duty << 6, false);
+        pulse.write_register(2, 0xF0, false);
+        pulse.write_register(3, frequency as u8, false);
+        pulse.write_register(4, 0x80 | (frequency >> 8) as u8, false);
+        pulse
+    }
+
+    #[test]
+    fn duty_cycles() {
+        for (duty, expected) in [(0, 1), (1, 2), (2, 4), (3, 6)] {
+            // Period of a single machine cycle per step.
+            let mut pulse = triggered(duty, 2047);
+            let high = (0..8)
+                .filter(|_| {
+                    pulse.tick();
+                    pulse.output() == 15
+                })
+                .count();
+            assert_eq!(high, expected, "duty {duty}");
+        }
+    }
+
+    #[test]
+    fn length_turns_channel_off() {
+        let mut pulse = triggered(2, 0);
+        // Length 2.
+        pulse.write_register(1, 0x80 | 0x3E, false);
+        pulse.write_register(4, 0xC0, false);
+        pulse.clock_length();
+        assert!(pulse.enabled());
+        pulse.clock_length();
+        assert!(!pulse.enabled());
+    }
+
+    #[test]
+    fn dac_off_turns_channel_off() {
+        let mut pulse = triggered(2, 0);
+        assert!(pulse.enabled());
+        pulse.write_register(2, 0x08, false);
+        assert!(pulse.enabled());
+        pulse.write_register(2, 0x00, false);
+        assert!(!pulse.enabled());
+        pulse.write_register(4, 0x80, false);
+        assert!(!pulse.enabled());
+    }
+
+    #[test]
+    fn envelope_decreases_volume() {
+        let mut pulse = triggered(3, 2047);
+        pulse.write_register(2, 0x21, false);
+        pulse.write_register(4, 0x87, false);
+        pulse.tick();
+        assert_eq!(pulse.output(), 2);
+        pulse.clock_envelope();
+        assert_eq!(pulse.output(), 1);
+        pulse.clock_envelope();
+        pulse.clock_envelope();
+        assert_eq!(pulse.output(), 0);
+    }
+
+    #[test]
+    fn sweep_increases_frequency_until_overflow() {
+        let mut pulse = Pulse::with_sweep();
+        // Period 1, shift 1.
+        pulse.write_register(0, 0x11, false);
+        pulse.write_register(2, 0xF0, false);
+        pulse.write_register(3, 0x00, false);
+        pulse.write_register(4, 0x84, false);
+        assert_eq!(pulse.frequency, 0x400);
+        pulse.clock_sweep();
+        // 0x600 is written back, 0x900 overflows on the check that follows.
+        assert_eq!(pulse.frequency, 0x600);
+        assert!(!pulse.enabled());
+    }
+
+    #[test]
+    fn sweep_overflow_on_trigger() {
+        let mut pulse = Pulse::with_sweep();
+        pulse.write_register(0, 0x01, false);
+        pulse.write_register(2, 0xF0, false);
+        pulse.write_register(3, 0xFF, false);
+        pulse.write_register(4, 0x87, false);
+        assert!(!pulse.enabled());
+    }
+
+    #[test]
+    fn sweep_negate_cleared_after_calculation() {
+        let mut pulse = Pulse::with_sweep();
+        pulse.write_register(0, 0x19, false);
+        pulse.write_register(2, 0xF0, false);
+        pulse.write_register(4, 0x84, false);
+        assert!(pulse.enabled());
+        pulse.write_register(0, 0x11, false);
+        assert!(!pulse.enabled());
+    }
+}
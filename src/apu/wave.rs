@@ -0,0 +1,284 @@
+//! Wave channel 3 (`NR30`-`NR34`). Plays 32 4-bit samples from wave RAM (0xFF30-0xFF3F),
+//! upper nibble first, at one of four output levels.
+
+use super::length::Length;
+
+/// Size of wave RAM, two samples per byte.
+pub const WAVE_RAM_SIZE: usize = 0x10;
+
+/// Wave RAM content after power on, it differs between units.
+const WAVE_RAM_INIT: [u8; WAVE_RAM_SIZE] = [
+    0x84, 0x40, 0x43, 0xAA, 0x2D, 0x78, 0x92, 0x3C, 0x60, 0x59, 0x59, 0xB0, 0x34, 0xB8, 0x2E, 0xDA,
+];
+
+/// Bits of `NR30`-`NR34` that always read as 1.
+const READ_MASKS: [u8; 5] = [0x7F, 0xFF, 0x9F, 0xFF, 0xBF];
+
+/// Dots from a trigger to the first sample read.
+const TRIGGER_DELAY: u16 = 6;
+
+#[derive(Debug, Clone)]
+pub struct Wave {
+    enabled: bool,
+    /// DAC power, bit 7 of `NR30`.
+    dac_enabled: bool,
+    /// Output level, bits 6-5 of `NR32`.
+    level: u8,
+    /// 11 bit frequency value from `NR33` and `NR34`.
+    frequency: u16,
+    /// Dots until the next sample is read.
+    timer: u16,
+    /// Index of the sample last read, 0-31.
+    position: u8,
+    /// Sample last read, played until the next one.
+    sample: u8,
+    /// A sample was read during the current machine cycle, the only time the cpu can
+    /// access wave RAM while the channel plays.
+    sample_read: bool,
+    length: Length,
+    ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wave {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            level: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            sample_read: false,
+            length: Length::new(256),
+            ram: WAVE_RAM_INIT,
+        }
+    }
+
+    /// Reset all registers as the APU powers off, the length counter and wave RAM are kept.
+    pub fn power_off(&mut self) {
+        let mut length = self.length.clone();
+        length.power_off();
+        *self = Self {
+            length,
+            ram: self.ram,
+            ..Self::new()
+        };
+    }
+
+    /// Whether the channel is playing, as shown in `NR52`.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    /// Digital output, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.level == 0 {
+            return 0;
+        }
+        self.sample >> (self.level - 1)
+    }
+
+    /// Reads `NR30`-`NR34`, selected by `index`.
+    pub fn read_register(&self, index: u16) -> u8 {
+        let val = match index {
+            0 => u8::from(self.dac_enabled) << 7,
+            2 => self.level << 5,
+            4 => u8::from(self.length.enabled()) << 6,
+            _ => 0,
+        };
+        val | READ_MASKS[index as usize]
+    }
+
+    /// Writes `NR30`-`NR34`, selected by `index`. `extra_length_clock` is set if the next
+    /// frame sequencer step doesn't clock the length counter.
+    pub fn write_register(&mut self, index: u16, val: u8, extra_length_clock: bool) {
+        match index {
+            0 => {
+                self.dac_enabled = val & 0x80 != 0;
+                self.enabled &= self.dac_enabled;
+            }
+            1 => self.length.load(val),
+            2 => self.level = (val >> 5) & 0b11,
+            3 => self.frequency = self.frequency & 0x700 | u16::from(val),
+            4 => {
+                self.frequency = self.frequency & 0xFF | u16::from(val & 0b111) << 8;
+                let trigger = val & 0x80 != 0;
+                if self
+                    .length
+                    .write(val & 0x40 != 0, trigger, extra_length_clock)
+                {
+                    self.enabled = false;
+                }
+                if trigger {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Reads wave RAM. While the channel plays the cpu sees the byte the channel reads, if it
+    /// read one this cycle, or 0xFF.
+    pub fn read_ram(&self, index: usize) -> u8 {
+        if !self.enabled {
+            return self.ram[index];
+        }
+        if self.sample_read {
+            self.ram[usize::from(self.position / 2)]
+        } else {
+            0xFF
+        }
+    }
+
+    /// Writes wave RAM. While the channel plays the write goes to the byte the channel reads,
+    /// if it read one this cycle, or is dropped.
+    pub fn write_ram(&mut self, index: usize, val: u8) {
+        if !self.enabled {
+            self.ram[index] = val;
+        } else if self.sample_read {
+            self.ram[usize::from(self.position / 2)] = val;
+        }
+    }
+
+    fn trigger(&mut self) {
+        // Retriggering the DMG right as the channel reads a sample corrupts the start of wave
+        // RAM with the bytes around the one being read.
+        if self.enabled && self.timer <= 4 {
+            let next = usize::from((self.position + 1) % 32 / 2);
+            if next < 4 {
+                self.ram[0] = self.ram[next];
+            } else {
+                let start = next & !0b11;
+                self.ram.copy_within(start..start + 4, 0);
+            }
+        }
+
+        self.enabled = self.dac_enabled;
+        self.timer = self.period() + TRIGGER_DELAY;
+        self.position = 0;
+    }
+
+    /// Dots per sample.
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    /// Advance the frequency timer by one machine cycle.
+    pub fn tick(&mut self) {
+        self.sample_read = false;
+        if !self.enabled {
+            return;
+        }
+        let mut dots = 4;
+        while dots >= self.timer {
+            dots -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[usize::from(self.position / 2)];
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+            self.sample_read = true;
+        }
+        self.timer -= dots;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Wave;
+
+    /// Wave channel with a ramp in wave RAM, triggered at full volume.
+    fn triggered(frequency: u16) -> Wave {
+        let mut wave = Wave::new();
+        for index in 0..16 {
+            wave.write_ram(index, (index as u8 * 2) << 4 | (index as u8 * 2 + 1));
+        }
+        wave.write_register(0, 0x80, false);
+        wave.write_register(2, 0x20, false);
+        wave.write_register(3, frequency as u8, false);
+        wave.write_register(4, 0x80 | (frequency >> 8) as u8, false);
+        wave
+    }
+
+    #[test]
+    fn plays_wave_ram_at_output_level() {
+        // Two dots per sample.
+        let mut wave = triggered(2047);
+        // The trigger delays the first read, which starts at the second sample.
+        wave.tick();
+        wave.tick();
+        assert_eq!(wave.output(), 1);
+        wave.tick();
+        assert_eq!(wave.output(), 3);
+        wave.tick();
+        wave.tick();
+        assert_eq!(wave.output(), 7);
+        wave.write_register(2, 0x40, false);
+        assert_eq!(wave.output(), 3);
+        wave.write_register(2, 0x60, false);
+        assert_eq!(wave.output(), 1);
+        wave.write_register(2, 0x00, false);
+        assert_eq!(wave.output(), 0);
+    }
+
+    #[test]
+    fn dac_off_turns_channel_off() {
+        let mut wave = triggered(0);
+        assert!(wave.enabled());
+        wave.write_register(0, 0x00, false);
+        assert!(!wave.enabled());
+    }
+
+    #[test]
+    fn length_of_256() {
+        let mut wave = triggered(0);
+        wave.write_register(1, 0x00, false);
+        wave.write_register(4, 0x40, false);
+        for _ in 0..255 {
+            wave.clock_length();
+        }
+        assert!(wave.enabled());
+        wave.clock_length();
+        assert!(!wave.enabled());
+    }
+
+    #[test]
+    fn ram_access_while_playing() {
+        let mut wave = triggered(0);
+        assert_eq!(wave.read_ram(5), 0xFF);
+        wave.write_ram(5, 0x00);
+        assert_eq!(wave.ram[5], 0xAB);
+
+        // Run until the first sample is read.
+        while !wave.sample_read {
+            wave.tick();
+        }
+        assert_eq!(wave.position, 1);
+        assert_eq!(wave.read_ram(5), 0x01);
+        wave.write_ram(5, 0x42);
+        assert_eq!(wave.ram[0], 0x42);
+
+        wave.write_register(0, 0x00, false);
+        assert_eq!(wave.read_ram(5), 0xAB);
+    }
+}
@@ -0,0 +1,158 @@
+//! Headless runner, runs a ROM without creating a window. Serial output is written to stdout,
+//! the final frame can be saved as PNG. With `--test` the ROM is run as part of a test suite
+//! and the exit code reports whether it passed.
+
+#![forbid(unsafe_code)]
+#![deny(nonstandard_style)]
+#![warn(clippy::pedantic, clippy::unwrap_used)]
+#![allow(clippy::cast_possible_truncation)]
+
+use std::{fs, io::BufWriter, path::PathBuf, process::ExitCode};
+
+use anyhow::Context;
+use clap::{Parser, ValueEnum};
+use rustboy::{
+    debug::{self, Doctor},
+    frontend::{NullFrontend, GREYSCALE},
+    gb::{self, Event},
+    harness::{self, Report},
+    ppu::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+    Gameboy,
+};
+use tracing_subscriber::EnvFilter;
+
+/// Command line arguments, parsed by [`clap`].
+#[derive(Parser, Debug)]
+struct Args {
+    rom_path: PathBuf,
+    /// Number of frames to run for, in test mode the number of frames until the ROM times out.
+    #[arg(short, long)]
+    frames: Option<u64>,
+    /// Run the ROM as test of the given suite.
+    #[arg(short, long, value_enum)]
+    test: Option<TestSuite>,
+    /// Stop as soon as the serial output contains this text.
+    #[arg(long)]
+    until_serial: Option<String>,
+    /// Write the final frame to this PNG file.
+    #[arg(short, long)]
+    screenshot: Option<PathBuf>,
+    /// Compare execution against this gameboy doctor reference log (plain or gzipped).
+    #[arg(long)]
+    gbd_reference: Option<PathBuf>,
+    #[arg(long, action)]
+    enable_trace: bool,
+    /// Draw through the cycle-accurate pixel FIFO, for mid-scanline effects.
+    #[arg(long, action)]
+    pixel_fifo: bool,
+}
+
+/// Test ROM suites supported by `--test`.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum TestSuite {
+    Blargg,
+    Mooneye,
+}
+
+/// Frames to run for, if not given.
+const DEFAULT_FRAMES: u64 = 600;
+
+fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+    if args.enable_trace {
+        tracing_subscriber::fmt::fmt()
+            .with_env_filter(EnvFilter::from_default_env())
+            .without_time()
+            .init();
+    }
+    tracing::info!(?args, "starting headless emulator");
+
+    let rom = fs::read(&args.rom_path).context("cannot read ROM")?;
+    let cfg = gb::Config {
+        pixel_fifo: args.pixel_fifo,
+        ..gb::Config::default()
+    };
+    let mut gb = Gameboy::new(&rom, Box::new(NullFrontend), cfg);
+    if let Some(path) = &args.gbd_reference {
+        let doctor =
+            Doctor::open(path, debug::DEFAULT_HISTORY_LEN).context("cannot open reference log")?;
+        gb.cpu.doctor = Some(doctor);
+        gb.cpu.bus.set_ly_stub(true);
+    }
+
+    let exit_code = if let Some(suite) = args.test {
+        run_test(&mut gb, suite, args.frames)
+    } else {
+        run(
+            &mut gb,
+            args.frames.unwrap_or(DEFAULT_FRAMES),
+            args.until_serial.as_deref(),
+        )
+    };
+
+    if let Some(path) = &args.screenshot {
+        write_png(gb.framebuffer(), path).context("cannot write screenshot")?;
+    }
+
+    Ok(exit_code)
+}
+
+/// Run for `frames` frames, or until `until_serial` was printed. Fails if execution diverged
+/// from the doctor's reference log.
+fn run(gb: &mut Gameboy, frames: u64, until_serial: Option<&str>) -> ExitCode {
+    let mut serial = Vec::new();
+    for _ in 0..frames {
+        let frame = gb.run_frame();
+        gb::print_serial(&frame.events);
+        if let Some(divergence) = gb::divergence(&frame.events) {
+            eprint!("{divergence}");
+            return ExitCode::FAILURE;
+        }
+        serial.extend(frame.events.iter().filter_map(Event::serial_byte));
+
+        if let Some(needle) = until_serial {
+            if String::from_utf8_lossy(&serial).contains(needle) {
+                break;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Run the ROM as test of `suite` and print the report.
+fn run_test(gb: &mut Gameboy, suite: TestSuite, timeout_frames: Option<u64>) -> ExitCode {
+    let report: Report = match suite {
+        TestSuite::Blargg => harness::blargg::run(
+            gb,
+            timeout_frames.unwrap_or(harness::blargg::DEFAULT_TIMEOUT_FRAMES),
+        ),
+        TestSuite::Mooneye => harness::mooneye::run(
+            gb,
+            timeout_frames.unwrap_or(harness::mooneye::DEFAULT_TIMEOUT_FRAMES),
+        ),
+    };
+
+    println!("{}", report.output.trim_end());
+    println!("{} after {} frames", report.outcome, report.frames);
+    ExitCode::from(report.outcome.exit_code())
+}
+
+/// Write the framebuffer as greyscale PNG to `path`.
+fn write_png(framebuffer: &FrameBuffer, path: &PathBuf) -> anyhow::Result<()> {
+    let file = fs::File::create(path)?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        SCREEN_WIDTH as u32,
+        SCREEN_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let pixels: Vec<u8> = framebuffer
+        .pixels()
+        .iter()
+        .map(|pixel| GREYSCALE[(pixel.shade & 0b11) as usize])
+        .collect();
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(())
+}
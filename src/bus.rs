@@ -0,0 +1,109 @@
+//! Memory bus abstraction. The cpu only accesses memory through a [`Bus`], so the SM83 core
+//! can run against the Gameboy memory map ([`Mmu`](crate::mmu::Mmu)), flat RAM for tests, an
+//! instrumented bus or other SM83-based hardware.
+
+use crate::cpu::utils;
+
+/// Size of the whole address space.
+const ADDRESS_SPACE_SIZE: usize = 0x10000;
+
+/// Memory bus the cpu reads from and writes to.
+pub trait Bus {
+    /// Read a byte at `address`.
+    fn read_u8(&self, address: u16) -> u8;
+    /// Write a byte to `address`.
+    fn write_u8(&mut self, address: u16, val: u8);
+    /// Advance the hardware behind the bus by one machine cycle. Interrupts are requested
+    /// by setting their bit in IF (0xFF0F).
+    fn tick(&mut self);
+
+    /// Machine cycle of the cpu reading `address`. Unlike [`Bus::read_u8`], which the cpu
+    /// also uses to peek at registers like IF, this is an access of the instruction.
+    fn tick_read(&mut self, address: u16) -> u8 {
+        self.tick();
+        self.read_u8(address)
+    }
+
+    /// Machine cycle of the cpu writing `val` to `address`.
+    fn tick_write(&mut self, address: u16, val: u8) {
+        self.tick();
+        self.write_u8(address, val);
+    }
+
+    /// Read a little endian u16 at `address`.
+    fn read_u16(&self, address: u16) -> u16 {
+        let l = self.read_u8(address);
+        let h = self.read_u8(address.wrapping_add(1));
+        utils::merge_u8s(h, l)
+    }
+
+    /// Write `val` little endian to `address`.
+    fn write_u16(&mut self, address: u16, val: u16) {
+        let (h, l) = utils::split_u16(val);
+        self.write_u8(address, l);
+        self.write_u8(address.wrapping_add(1), h);
+    }
+}
+
+/// Memory access the cpu made during a machine cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Internal cycle without a memory access.
+    Idle,
+    /// Read of the value at the address.
+    Read(u16, u8),
+    /// Write of the value to the address.
+    Write(u16, u8),
+}
+
+/// Flat, zeroed 64 KiB RAM without anything mapped into it. Logs the access the cpu made in
+/// every machine cycle, peeks outside of a cycle aren't logged.
+pub struct FlatBus {
+    memory: Box<[u8]>,
+    cycles: Vec<Access>,
+}
+
+impl FlatBus {
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0; ADDRESS_SPACE_SIZE].into_boxed_slice(),
+            cycles: Vec::new(),
+        }
+    }
+
+    /// Take the accesses of the cycles run so far.
+    pub fn take_cycles(&mut self) -> Vec<Access> {
+        std::mem::take(&mut self.cycles)
+    }
+}
+
+impl Default for FlatBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for FlatBus {
+    fn read_u8(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write_u8(&mut self, address: u16, val: u8) {
+        self.memory[address as usize] = val;
+    }
+
+    fn tick(&mut self) {
+        self.cycles.push(Access::Idle);
+    }
+
+    fn tick_read(&mut self, address: u16) -> u8 {
+        let val = self.read_u8(address);
+        self.cycles.push(Access::Read(address, val));
+        val
+    }
+
+    fn tick_write(&mut self, address: u16, val: u8) {
+        self.write_u8(address, val);
+        self.cycles.push(Access::Write(address, val));
+    }
+}
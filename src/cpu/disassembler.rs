@@ -1,5 +1,3 @@
-use std::u16;
-
 use super::registers::PC_INIT_VAL;
 
 pub type Instruction = (
@@ -543,6 +541,11 @@ pub fn decode_instruction(opcode: u8) -> &'static str {
     INSTRUCTIONS[opcode as usize].mnemonic()
 }
 
+/// Decode an instruction prefixed by `0xCB`.
+pub fn decode_extended_instruction(opcode: u8) -> &'static str {
+    EXTENDED_INSTRUCTIONS[opcode as usize].mnemonic()
+}
+
 pub fn disassemble_rom(rom: &[u8]) -> Vec<String> {
     let mut addr = PC_INIT_VAL;
     let mut ret = Vec::with_capacity(rom.len());
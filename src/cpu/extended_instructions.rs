@@ -1,8 +1,10 @@
+use crate::bus::Bus;
+
 use super::{disassembler::decode_instruction, Cpu};
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     #[tracing::instrument(name = "extended", target = "", skip(self), fields(c))]
-    pub fn exec_cb_instruction(&mut self) -> u8 {
+    pub fn exec_cb_instruction(&mut self) {
         let opcode = self.read_u8_at_pc_and_increase();
         let mnemonic = decode_instruction(opcode);
         let pc = self.registers.pc;
@@ -56,14 +58,12 @@ impl Cpu {
                 if *self.registers.h_index(dst_idx) == 0 {
                     self.registers.set_flag_z(true);
                 }
-                1
             }
             0x18..=0x1f => {
                 self.rr(dst_idx);
                 if *self.registers.h_index(dst_idx) == 0 {
                     self.registers.set_flag_z(true);
                 }
-                1
             }
             0x20..=0x27 => self.sla(dst_idx),
             0x28..=0x2f => self.sra(dst_idx),
@@ -102,18 +102,16 @@ impl Cpu {
         val | bit
     }
 
-    fn set(&mut self, bit_idx: u8, register_idx: u8) -> u8 {
+    fn set(&mut self, bit_idx: u8, register_idx: u8) {
         *self.registers.h_index_mut(register_idx) =
             Self::set_bit(*self.registers.h_index(register_idx), bit_idx);
-        1
     }
 
-    fn set_hl(&mut self, bit_idx: u8) -> u8 {
+    fn set_hl(&mut self, bit_idx: u8) {
         let address = self.registers.get_hl();
-        let val = self.mmu.read_u8(address);
+        let val = self.read_u8(address);
         let result = Self::set_bit(val, bit_idx);
-        self.mmu.write_u8(address, result);
-        1
+        self.write_u8(address, result);
     }
 
     fn reset_bit(val: u8, idx: u8) -> u8 {
@@ -122,18 +120,16 @@ impl Cpu {
         (val | bit) ^ bit
     }
 
-    fn res(&mut self, bit_idx: u8, register_idx: u8) -> u8 {
+    fn res(&mut self, bit_idx: u8, register_idx: u8) {
         *self.registers.h_index_mut(register_idx) =
             Self::reset_bit(*self.registers.h_index(register_idx), bit_idx);
-        1
     }
 
-    fn res_hl(&mut self, bit_idx: u8) -> u8 {
+    fn res_hl(&mut self, bit_idx: u8) {
         let address = self.registers.get_hl();
-        let val = self.mmu.read_u8(address);
+        let val = self.read_u8(address);
         let result = Self::reset_bit(val, bit_idx);
-        self.mmu.write_u8(address, result);
-        1
+        self.write_u8(address, result);
     }
 
     fn sll_val(&mut self, val: u8) -> u8 {
@@ -145,17 +141,16 @@ impl Cpu {
         result
     }
 
-    fn sll(&mut self, register_idx: u8) -> u8 {
+    fn sll(&mut self, register_idx: u8) {
         *self.registers.h_index_mut(register_idx) =
             self.sll_val(*self.registers.h_index(register_idx));
-        1
     }
 
-    fn sll_hl(&mut self) -> u8 {
+    fn sll_hl(&mut self) {
         let address = self.registers.get_hl();
-        let result = self.sll_val(self.mmu.read_u8(address));
-        self.mmu.write_u8(address, result);
-        2
+        let val = self.read_u8(address);
+        let result = self.sll_val(val);
+        self.write_u8(address, result);
     }
 
     fn srl_val(&mut self, val: u8) -> u8 {
@@ -167,17 +162,16 @@ impl Cpu {
         result
     }
 
-    fn srl(&mut self, register_idx: u8) -> u8 {
+    fn srl(&mut self, register_idx: u8) {
         *self.registers.h_index_mut(register_idx) =
             self.srl_val(*self.registers.h_index(register_idx));
-        1
     }
 
-    fn srl_hl(&mut self) -> u8 {
+    fn srl_hl(&mut self) {
         let address = self.registers.get_hl();
-        let result = self.srl_val(self.mmu.read_u8(address));
-        self.mmu.write_u8(address, result);
-        2
+        let val = self.read_u8(address);
+        let result = self.srl_val(val);
+        self.write_u8(address, result);
     }
 
     fn sla_val(&mut self, val: u8) -> u8 {
@@ -189,17 +183,16 @@ impl Cpu {
         result
     }
 
-    fn sla(&mut self, register_idx: u8) -> u8 {
+    fn sla(&mut self, register_idx: u8) {
         *self.registers.h_index_mut(register_idx) =
             self.sla_val(*self.registers.h_index(register_idx));
-        1
     }
 
-    fn sla_hl(&mut self) -> u8 {
+    fn sla_hl(&mut self) {
         let address = self.registers.get_hl();
-        let result = self.sla_val(self.mmu.read_u8(address));
-        self.mmu.write_u8(address, result);
-        2
+        let val = self.read_u8(address);
+        let result = self.sla_val(val);
+        self.write_u8(address, result);
     }
 
     fn sra_val(&mut self, val: u8) -> u8 {
@@ -211,62 +204,58 @@ impl Cpu {
         result
     }
 
-    fn sra(&mut self, register_idx: u8) -> u8 {
+    fn sra(&mut self, register_idx: u8) {
         *self.registers.h_index_mut(register_idx) =
             self.sra_val(*self.registers.h_index(register_idx));
-        1
     }
 
-    fn sra_hl(&mut self) -> u8 {
+    fn sra_hl(&mut self) {
         let address = self.registers.get_hl();
-        let result = self.sra_val(self.mmu.read_u8(address));
-        self.mmu.write_u8(address, result);
-        2
+        let val = self.read_u8(address);
+        let result = self.sra_val(val);
+        self.write_u8(address, result);
     }
 
-    fn rr_hl(&mut self) -> u8 {
+    fn rr_hl(&mut self) {
         let address = self.registers.get_hl();
-        let result = self.rr_val(self.mmu.read_u8(address));
-        self.mmu.write_u8(address, result);
-        2
+        let val = self.read_u8(address);
+        let result = self.rr_val(val);
+        self.write_u8(address, result);
     }
 
-    fn rl_hl(&mut self) -> u8 {
+    fn rl_hl(&mut self) {
         let address = self.registers.get_hl();
-        let result = self.rl_val(self.mmu.read_u8(address));
-        self.mmu.write_u8(address, result);
-        2
+        let val = self.read_u8(address);
+        let result = self.rl_val(val);
+        self.write_u8(address, result);
     }
 
-    fn rrc_hl(&mut self) -> u8 {
+    fn rrc_hl(&mut self) {
         let address = self.registers.get_hl();
-        let result = self.rrc_val(self.mmu.read_u8(address));
-        self.mmu.write_u8(address, result);
-        2
+        let val = self.read_u8(address);
+        let result = self.rrc_val(val);
+        self.write_u8(address, result);
     }
 
-    fn rlc_hl(&mut self) -> u8 {
+    fn rlc_hl(&mut self) {
         let address = self.registers.get_hl();
-        let result = self.rlc_val(self.mmu.read_u8(address));
-        self.mmu.write_u8(address, result);
-        2
+        let val = self.read_u8(address);
+        let result = self.rlc_val(val);
+        self.write_u8(address, result);
     }
 
-    fn test_bit(&mut self, bit_idx: u8, val: u8) -> u8 {
+    fn test_bit(&mut self, bit_idx: u8, val: u8) {
         self.registers.set_flag_z(((val >> bit_idx) & 1) == 1);
         self.registers.set_flag_h(true);
         self.registers.set_flag_n(false);
-        3
     }
 
-    fn bit_hl(&mut self, bit_idx: u8) -> u8 {
-        let val = self.mmu.read_u8(self.registers.get_hl());
+    fn bit_hl(&mut self, bit_idx: u8) {
+        let val = self.read_u8(self.registers.get_hl());
         self.test_bit(bit_idx, val);
-        1
     }
 
-    fn bit(&mut self, bit_idx: u8, register_idx: u8) -> u8 {
+    fn bit(&mut self, bit_idx: u8, register_idx: u8) {
         self.test_bit(bit_idx, *self.registers.h_index(register_idx));
-        1
     }
 }
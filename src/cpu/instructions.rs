@@ -1,21 +1,33 @@
+use crate::bus::Bus;
+
 use super::disassembler::decode_instruction;
-use super::{registers::REGISTER_A_INDEX, utils::merge_u8s, Cpu};
+use super::{
+    registers::REGISTER_A_INDEX,
+    utils::{merge_u8s, split_u16},
+    Cpu, WRAM_DIV_OFFSET, WRAM_P1_OFFSET,
+};
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     /// XXX dst, src
     /// TODO: Streamline everything
-    #[allow(clippy::too_many_lines)]
+    #[allow(clippy::too_many_lines, clippy::match_overlapping_arm)]
     #[tracing::instrument(name = "exec", target = "", skip(self), fields(c))]
     pub fn exec_instruction(&mut self) -> u8 {
+        let start = self.m_cycles;
         self.cycle += 1;
-        if self.halted {
-            return 0;
-        }
 
         if self.debug.gb_doc_enable {
             self.gb_doctor_log();
         }
-        let opcode = self.read_u8_at_pc_and_increase();
+        if self.doctor.is_some() {
+            self.gb_doctor_check();
+        }
+        let opcode = if self.halt_bug {
+            self.halt_bug = false;
+            self.read_u8(self.registers.pc)
+        } else {
+            self.read_u8_at_pc_and_increase()
+        };
         let dst_idx = opcode >> 4;
         let src_idx = opcode & 0xf;
         let mnemonic = decode_instruction(opcode);
@@ -26,7 +38,7 @@ impl Cpu {
         tracing::Span::current().record("pc", format!("{pc:0>4x}"));
         tracing::Span::current().record("pc_mem", format!("{pc_mem:0>4x}"));
         tracing::Span::current().record("op", format!("{opcode:0>2x}"));
-        tracing::Span::current().record("mnemonic", format!("{mnemonic}"));
+        tracing::Span::current().record("mnemonic", mnemonic.to_string());
 
         if self.schedule_ei {
             self.schedule_ei = false;
@@ -100,6 +112,7 @@ impl Cpu {
             0x3d => self.dec_a(),
             0x3e => self.ld_a_d8(),
             0x3f => self.ccf(),
+            0x40 => self.ld_b_b(),
             0x46 => self.ld_b_hl_ptr(),
             0x4e => self.ld_c_hl_ptr(),
             0x56 => self.ld_d_hl_ptr(),
@@ -190,6 +203,8 @@ impl Cpu {
                 panic!("{msg}")
             }
         }
+
+        (self.m_cycles - start) as u8
     }
 
     pub fn gb_doctor_format(&self) -> String {
@@ -204,37 +219,91 @@ impl Cpu {
             self.registers.l,
             self.registers.sp,
             self.registers.pc,
-            self.mmu.read_u8(self.registers.pc),
-            self.mmu.read_u8(self.registers.pc + 1),
-            self.mmu.read_u8(self.registers.pc + 2),
-            self.mmu.read_u8(self.registers.pc + 3),)
+            self.bus.read_u8(self.registers.pc),
+            self.bus.read_u8(self.registers.pc + 1),
+            self.bus.read_u8(self.registers.pc + 2),
+            self.bus.read_u8(self.registers.pc + 3))
     }
 
     pub fn gb_doctor_log(&self) {
         println!("{}", self.gb_doctor_format());
     }
 
-    pub fn nop() -> u8 {
-        1
+    /// Compare the current state against the doctor's reference log. Stops comparing
+    /// after the first divergence or once the reference log ended.
+    pub fn gb_doctor_check(&mut self) {
+        let line = self.gb_doctor_format();
+        let pc = self.registers.pc;
+        let pc_mem = [0, 1, 2, 3].map(|i| self.bus.read_u8(pc.wrapping_add(i)));
+        let Some(doctor) = &mut self.doctor else {
+            return;
+        };
+
+        match doctor.check(&line, pc, pc_mem) {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::info!("reference log ended, stopping comparison");
+                self.doctor = None;
+            }
+            Err(divergence) => {
+                tracing::error!(
+                    "diverged from reference log at instruction {}",
+                    divergence.instruction
+                );
+                self.divergence = Some(divergence);
+                self.doctor = None;
+            }
+        }
     }
 
-    pub fn stop(&mut self) -> u8 {
-        self.halted = true;
-        let after_stop = self.read_u8_at_pc_and_increase();
-        if after_stop != 0x00 {
-            tracing::warn!("corrupted stop");
+    pub fn nop() {}
+
+    /// `LD B,B` does nothing, but serves as software breakpoint if enabled.
+    pub fn ld_b_b(&mut self) {
+        if self.breakpoints_enable {
+            tracing::debug!("breakpoint hit");
+            self.breakpoint_hit = true;
+        }
+    }
+
+    /// `STOP` enters low power mode until a button is pressed. As on the DMG, the byte after it
+    /// is only skipped without an interrupt pending, and with a button already held it enters
+    /// HALT mode instead (or does nothing with an interrupt pending) and DIV keeps running.
+    pub fn stop(&mut self) {
+        let button_held = self.bus.read_u8(WRAM_P1_OFFSET) & 0x0F != 0x0F;
+        let interrupt_pending = self.pending_interrupts() != 0;
+        if !interrupt_pending {
+            let after_stop = self.read_u8_at_pc_and_increase();
+            if after_stop != 0x00 {
+                tracing::warn!("corrupted stop");
+            }
+        }
+        if button_held {
+            if !interrupt_pending {
+                tracing::debug!("button held, halting instead of stopping");
+                self.halted = true;
+            }
+            return;
         }
 
-        1
+        tracing::debug!("stopping");
+        self.bus.write_u8(WRAM_DIV_OFFSET, 0);
+        self.stopped = true;
     }
 
-    pub fn hlt(&mut self) -> u8 {
+    /// `HALT` waits for an interrupt. With IME disabled and an interrupt already pending it
+    /// doesn't halt, but triggers the HALT bug instead.
+    pub fn hlt(&mut self) {
+        if !self.interrupt_master_enable && self.pending_interrupts() != 0 {
+            tracing::debug!("halt bug");
+            self.halt_bug = true;
+            return;
+        }
         tracing::debug!("halting");
         self.halted = true;
-        4
     }
 
-    pub fn daa(&mut self) -> u8 {
+    pub fn daa(&mut self) {
         if !self.registers.get_flag_n() {
             if self.registers.get_flag_c() || self.registers.a > 0x99 {
                 self.registers.a = self.registers.a.wrapping_add(0x60);
@@ -254,18 +323,16 @@ impl Cpu {
             self.registers.set_flag_h(false);
         }
         self.registers.set_flag_z(self.registers.a == 0);
-        2
     }
 
-    pub fn ret(&mut self) -> u8 {
+    pub fn ret(&mut self) {
         self.registers.pc = self.pop_stack_u16();
-        4
+        self.tick();
     }
 
-    pub fn rst(&mut self, address: u16) -> u8 {
+    pub fn rst(&mut self, address: u16) {
         self.push_stack_u16(self.registers.get_pc().wrapping_add(1));
         self.registers.set_pc(address);
-        3
     }
 
     pub fn inc16(val: u16) -> u16 {
@@ -276,53 +343,51 @@ impl Cpu {
         val.wrapping_sub(1)
     }
 
-    pub fn call(&mut self, addr: u16) -> u8 {
+    pub fn call(&mut self, addr: u16) {
         self.push_stack_u16(self.registers.pc);
         self.registers.pc = addr;
-        4
     }
 
-    pub fn call_a16(&mut self) -> u8 {
+    pub fn call_a16(&mut self) {
         let addr = self.read_u16_at_pc_and_increase();
-        self.call(addr)
+        self.call(addr);
     }
 
-    pub fn call_nz_a16(&mut self) -> u8 {
+    pub fn call_nz_a16(&mut self) {
         let addr = self.read_u16_at_pc_and_increase();
         if self.registers.get_flag_z() {
-            return 4;
+            return;
         }
-        self.call(addr)
+        self.call(addr);
     }
 
-    pub fn call_z_a16(&mut self) -> u8 {
+    pub fn call_z_a16(&mut self) {
         let addr = self.read_u16_at_pc_and_increase();
         if !self.registers.get_flag_z() {
-            return 4;
+            return;
         }
-        self.call(addr)
+        self.call(addr);
     }
 
-    pub fn call_nc_a16(&mut self) -> u8 {
+    pub fn call_nc_a16(&mut self) {
         let addr = self.read_u16_at_pc_and_increase();
         if self.registers.get_flag_c() {
-            return 4;
+            return;
         }
-        self.call(addr)
+        self.call(addr);
     }
 
-    pub fn call_c_a16(&mut self) -> u8 {
+    pub fn call_c_a16(&mut self) {
         let addr = self.read_u16_at_pc_and_increase();
         if !self.registers.get_flag_c() {
-            return 4;
+            return;
         }
-        self.call(addr)
+        self.call(addr);
     }
 
     /// `LD dst src`
-    pub fn ld(src: u8, dst: &mut u8) -> u8 {
+    pub fn ld(src: u8, dst: &mut u8) {
         *dst = src;
-        1
     }
 
     pub fn inc8(&mut self, val: u8) -> u8 {
@@ -333,25 +398,22 @@ impl Cpu {
         w
     }
 
-    pub fn cpl(&mut self) -> u8 {
+    pub fn cpl(&mut self) {
         self.registers.set_flag_n(true);
         self.registers.set_flag_h(true);
         self.registers.a ^= 0xFF;
-        1
     }
 
-    pub fn ccf(&mut self) -> u8 {
+    pub fn ccf(&mut self) {
         self.registers.set_flag_n(false);
         self.registers.set_flag_h(false);
         self.registers.set_flag_c(!self.registers.get_flag_c());
-        1
     }
 
-    pub fn scf(&mut self) -> u8 {
+    pub fn scf(&mut self) {
         self.registers.set_flag_n(false);
         self.registers.set_flag_h(false);
         self.registers.set_flag_c(true);
-        1
     }
 
     pub fn dec8(&mut self, val: u8) -> u8 {
@@ -363,6 +425,7 @@ impl Cpu {
     }
 
     pub fn add16(&mut self, val: u16) {
+        self.tick();
         let hl = self.registers.get_hl();
         let sum = val.wrapping_add(hl);
         self.registers.set_flag_h(Self::check_add_u16_hc(val, hl));
@@ -372,18 +435,15 @@ impl Cpu {
         self.registers.set_hl(sum);
     }
 
-    pub fn add8c(&mut self, val: u8) -> u8 {
+    pub fn add8c(&mut self, val: u8) {
         self.add8(val.wrapping_add(self.registers.get_flag_c().into()));
-
-        1
     }
 
-    pub fn sub8c(&mut self, val: u8) -> u8 {
+    pub fn sub8c(&mut self, val: u8) {
         self.sub8(val.wrapping_sub(self.registers.get_flag_c().into()));
-        1
     }
 
-    pub fn add8(&mut self, val: u8) -> u8 {
+    pub fn add8(&mut self, val: u8) {
         let a = self.registers.a;
         let result = a.wrapping_add(val);
         self.registers.set_flag_h(Self::check_add_u8_hc(a, val));
@@ -392,11 +452,9 @@ impl Cpu {
         self.registers
             .set_flag_c(u16::from(val) + u16::from(a) > 0xFF);
         self.registers.a = result;
-
-        1
     }
 
-    pub fn sub8(&mut self, val: u8) -> u8 {
+    pub fn sub8(&mut self, val: u8) {
         let a = self.registers.a;
         let result = a.wrapping_sub(val);
         self.registers.set_flag_h(Self::check_sub_u8_hc(a, val));
@@ -404,131 +462,119 @@ impl Cpu {
         self.registers.set_flag_n(true);
         self.registers.set_flag_c(u16::from(val) > u16::from(a));
         self.registers.a = result;
-
-        1
     }
 
-    pub fn jp(&mut self, address: u16) -> u8 {
+    pub fn jp(&mut self, address: u16) {
         self.registers.set_pc(address);
-        4
     }
 
-    pub fn jp_nz_a16(&mut self) -> u8 {
+    pub fn jp_nz_a16(&mut self) {
         let address = self.read_u16_at_pc_and_increase();
         if self.registers.get_flag_z() {
-            return 3;
+            return;
         }
-        self.jp(address)
+        self.tick();
+        self.jp(address);
     }
 
-    pub fn jp_nc_a16(&mut self) -> u8 {
+    pub fn jp_nc_a16(&mut self) {
         let address = self.read_u16_at_pc_and_increase();
         if self.registers.get_flag_c() {
-            return 3;
+            return;
         }
-        self.jp(address)
+        self.tick();
+        self.jp(address);
     }
 
-    pub fn jp_z_a16(&mut self) -> u8 {
+    pub fn jp_z_a16(&mut self) {
         let address = self.read_u16_at_pc_and_increase();
         if !self.registers.get_flag_z() {
-            return 3;
+            return;
         }
-        self.jp(address)
+        self.tick();
+        self.jp(address);
     }
 
-    pub fn jp_c_a16(&mut self) -> u8 {
+    pub fn jp_c_a16(&mut self) {
         let address = self.read_u16_at_pc_and_increase();
         if !self.registers.get_flag_c() {
-            return 3;
+            return;
         }
-        self.jp(address)
+        self.tick();
+        self.jp(address);
     }
 
-    #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_possible_wrap)]
     pub fn jr(&mut self, val: u8) {
         let n = val as i8;
-        self.registers.pc = ((self.registers.pc as u32 as i32) + (n as i32)) as u16;
+        self.tick();
+        self.registers.pc = self.registers.pc.wrapping_add_signed(i16::from(n));
     }
 
-    pub fn xor(&mut self, val: u8) -> u8 {
+    pub fn xor(&mut self, val: u8) {
         self.registers.a ^= val;
         self.registers.set_flag_z(self.registers.a == 0);
         self.registers.set_flag_n(false);
         self.registers.set_flag_h(false);
         self.registers.set_flag_c(false);
-
-        1
     }
 
-    pub fn and(&mut self, val: u8) -> u8 {
+    pub fn and(&mut self, val: u8) {
         self.registers.a &= val;
         self.registers.set_flag_z(self.registers.a == 0);
         self.registers.set_flag_n(false);
         self.registers.set_flag_h(true);
         self.registers.set_flag_c(false);
-
-        1
     }
 
-    pub fn or(&mut self, val: u8) -> u8 {
+    pub fn or(&mut self, val: u8) {
         self.registers.a |= val;
         self.registers.set_flag_z(self.registers.a == 0);
         self.registers.set_flag_n(false);
         self.registers.set_flag_h(false);
         self.registers.set_flag_c(false);
-
-        1
     }
 
-    pub fn cp(&mut self, val: u8) -> u8 {
+    pub fn cp(&mut self, val: u8) {
         let a = self.registers.a;
         let result = a.wrapping_sub(val);
         self.registers.set_flag_h(Self::check_sub_u8_hc(a, val));
         self.registers.set_flag_z(result == 0);
         self.registers.set_flag_n(true);
         self.registers.set_flag_c(u16::from(val) > u16::from(a));
-
-        1
     }
 
-    pub fn ld_b_hl_ptr(&mut self) -> u8 {
-        self.registers.b = self.mmu.read_u8(self.registers.get_hl());
-        2
+    pub fn ld_b_hl_ptr(&mut self) {
+        self.registers.b = self.read_u8(self.registers.get_hl());
     }
 
-    pub fn ld_d_hl_ptr(&mut self) -> u8 {
-        self.registers.d = self.mmu.read_u8(self.registers.get_hl());
-        2
+    pub fn ld_d_hl_ptr(&mut self) {
+        self.registers.d = self.read_u8(self.registers.get_hl());
     }
 
-    pub fn ld_h_hl_ptr(&mut self) -> u8 {
-        self.registers.h = self.mmu.read_u8(self.registers.get_hl());
-        2
+    pub fn ld_h_hl_ptr(&mut self) {
+        self.registers.h = self.read_u8(self.registers.get_hl());
     }
 
-    pub fn ld_c_hl_ptr(&mut self) -> u8 {
-        self.registers.c = self.mmu.read_u8(self.registers.get_hl());
-        2
+    pub fn ld_c_hl_ptr(&mut self) {
+        self.registers.c = self.read_u8(self.registers.get_hl());
     }
 
-    pub fn ld_e_hl_ptr(&mut self) -> u8 {
-        self.registers.e = self.mmu.read_u8(self.registers.get_hl());
-        2
+    pub fn ld_e_hl_ptr(&mut self) {
+        self.registers.e = self.read_u8(self.registers.get_hl());
     }
 
-    pub fn ld_l_hl_ptr(&mut self) -> u8 {
-        self.registers.l = self.mmu.read_u8(self.registers.get_hl());
-        2
+    pub fn ld_l_hl_ptr(&mut self) {
+        self.registers.l = self.read_u8(self.registers.get_hl());
     }
 
-    pub fn ld_a_hl_ptr(&mut self) -> u8 {
-        self.registers.a = self.mmu.read_u8(self.registers.get_hl());
-        2
+    pub fn ld_a_hl_ptr(&mut self) {
+        self.registers.a = self.read_u8(self.registers.get_hl());
     }
 
-    pub fn ld_hl_sp_r8(&mut self) -> u8 {
+    pub fn ld_hl_sp_r8(&mut self) {
         let e = self.read_u8_at_pc_and_increase();
+        self.tick();
         let result = u16::from(e) + self.registers.sp;
         self.registers.set_flag_z(false);
         self.registers.set_flag_n(false);
@@ -539,30 +585,28 @@ impl Cpu {
         self.registers
             .set_flag_c(u32::from(e) + u32::from(self.registers.get_sp()) > 0xFFFF);
         self.registers.set_hl(result);
-
-        3
     }
 
-    pub fn ld_sp_hl(&mut self) -> u8 {
+    pub fn ld_sp_hl(&mut self) {
+        self.tick();
         self.registers.sp = self.registers.get_hl();
-        2
     }
 
-    pub fn ld_a16_ptr_a(&mut self) -> u8 {
+    pub fn ld_a16_ptr_a(&mut self) {
         let addr = self.read_u16_at_pc_and_increase();
-        self.mmu.write_u8(addr, self.registers.a);
-        4
+        self.write_u8(addr, self.registers.a);
     }
 
-    pub fn ld_a_a16_ptr(&mut self) -> u8 {
+    pub fn ld_a_a16_ptr(&mut self) {
         let addr = self.read_u16_at_pc_and_increase();
-        let val = self.mmu.read_u8(addr);
+        let val = self.read_u8(addr);
         self.registers.a = val;
-        4
     }
 
-    pub fn add_sp_r8(&mut self) -> u8 {
+    pub fn add_sp_r8(&mut self) {
         let e = self.read_u8_at_pc_and_increase();
+        self.tick();
+        self.tick();
         self.registers.set_flag_z(false);
         self.registers.set_flag_n(false);
         self.registers.set_flag_h(Self::check_add_u16_hc(
@@ -575,379 +619,334 @@ impl Cpu {
         let result = self.registers.get_sp() + u16::from(e);
 
         self.registers.set_sp(result);
-        4
     }
 
-    pub fn ld_c_ptr_a(&mut self) -> u8 {
+    pub fn ld_c_ptr_a(&mut self) {
         let addr = merge_u8s(0xff, self.registers.c);
-        self.mmu.write_u8(addr, self.registers.a);
-        2
+        self.write_u8(addr, self.registers.a);
     }
 
-    pub fn ld_a_c_ptr(&mut self) -> u8 {
+    pub fn ld_a_c_ptr(&mut self) {
         let addr = merge_u8s(0xff, self.registers.c);
-        self.registers.a = self.mmu.read_u8(addr);
-        2
+        self.registers.a = self.read_u8(addr);
     }
 
-    pub fn ldh_a8_ptr_a(&mut self) -> u8 {
+    pub fn ldh_a8_ptr_a(&mut self) {
         let addr = merge_u8s(0xff, self.read_u8_at_pc_and_increase());
-        self.mmu.write_u8(addr, self.registers.a);
-        3
+        self.write_u8(addr, self.registers.a);
     }
 
-    pub fn ldh_a_a8_ptr(&mut self) -> u8 {
+    pub fn ldh_a_a8_ptr(&mut self) {
         let n = self.read_u8_at_pc_and_increase();
         let addr = 0xFF00 | (n as u16);
-        self.registers.a = self.mmu.read_u8(addr);
-        3
+        self.registers.a = self.read_u8(addr);
     }
 
-    pub fn reti(&mut self) -> u8 {
+    pub fn reti(&mut self) {
         self.ret();
         self.interrupt_master_enable = true;
-        4
     }
 
-    pub fn ld_hl_ptr_d8(&mut self) -> u8 {
+    pub fn ld_hl_ptr_d8(&mut self) {
         let val = self.read_u8_at_pc_and_increase();
         let hl = self.registers.get_hl();
-        self.mmu.write_u8(hl, val);
-        3
+        self.write_u8(hl, val);
     }
 
-    pub fn ld_a_bc_ptr(&mut self) -> u8 {
-        Self::ld(
-            self.mmu.read_u8(self.registers.get_bc()),
-            &mut self.registers.a,
-        );
-        2
+    pub fn ld_a_bc_ptr(&mut self) {
+        Self::ld(self.read_u8(self.registers.get_bc()), &mut self.registers.a);
     }
 
-    pub fn ld_a_de_ptr(&mut self) -> u8 {
-        Self::ld(
-            self.mmu.read_u8(self.registers.get_de()),
-            &mut self.registers.a,
-        );
-        2
+    pub fn ld_a_de_ptr(&mut self) {
+        Self::ld(self.read_u8(self.registers.get_de()), &mut self.registers.a);
     }
 
-    pub fn ld_b_d8(&mut self) -> u8 {
+    pub fn ld_b_d8(&mut self) {
         Self::ld(self.read_u8_at_pc_and_increase(), &mut self.registers.b);
-        2
     }
 
-    pub fn ld_d_d8(&mut self) -> u8 {
+    pub fn ld_d_d8(&mut self) {
         Self::ld(self.read_u8_at_pc_and_increase(), &mut self.registers.d);
-        2
     }
 
-    pub fn ld_h_d8(&mut self) -> u8 {
+    pub fn ld_h_d8(&mut self) {
         Self::ld(self.read_u8_at_pc_and_increase(), &mut self.registers.h);
-        2
     }
 
-    pub fn ld_hl_inc_ptr_a(&mut self) -> u8 {
+    pub fn ld_hl_inc_ptr_a(&mut self) {
         let hl = self.registers.get_hl();
         self.registers.set_hl(hl.wrapping_add(1));
-        self.mmu.write_u8(hl, self.registers.a);
-        2
+        self.write_u8(hl, self.registers.a);
     }
 
-    pub fn ld_hl_dec_ptr_a(&mut self) -> u8 {
+    pub fn ld_hl_dec_ptr_a(&mut self) {
         let hl = self.registers.get_hl();
-        self.mmu.write_u8(hl, self.registers.a);
+        self.write_u8(hl, self.registers.a);
         self.registers.set_hl(hl.wrapping_sub(1));
-        2
     }
 
-    pub fn ld_a_hl_inc_ptr(&mut self) -> u8 {
+    pub fn ld_a_hl_inc_ptr(&mut self) {
         let hl = self.registers.get_hl();
-        Self::ld(self.mmu.read_u8(hl), &mut self.registers.a);
+        Self::ld(self.read_u8(hl), &mut self.registers.a);
         self.registers.set_hl(hl.wrapping_add(1));
-        2
     }
 
-    pub fn ld_a_hl_dec_ptr(&mut self) -> u8 {
+    pub fn ld_a_hl_dec_ptr(&mut self) {
         let hl = self.registers.get_hl();
-        Self::ld(self.mmu.read_u8(hl), &mut self.registers.a);
+        Self::ld(self.read_u8(hl), &mut self.registers.a);
         self.registers.set_hl(hl.wrapping_sub(1));
-        2
     }
 
-    pub fn ld_de_ptr_a(&mut self) -> u8 {
+    pub fn ld_de_ptr_a(&mut self) {
         let val = self.registers.a;
-        self.mmu.write_u8(self.registers.get_de(), val);
-        2
+        self.write_u8(self.registers.get_de(), val);
     }
 
-    pub fn ld_c_d8(&mut self) -> u8 {
+    pub fn ld_c_d8(&mut self) {
         Self::ld(self.read_u8_at_pc_and_increase(), &mut self.registers.c);
-        2
     }
 
-    pub fn ld_e_d8(&mut self) -> u8 {
+    pub fn ld_e_d8(&mut self) {
         Self::ld(self.read_u8_at_pc_and_increase(), &mut self.registers.e);
-        2
     }
 
-    pub fn ld_l_d8(&mut self) -> u8 {
+    pub fn ld_l_d8(&mut self) {
         Self::ld(self.read_u8_at_pc_and_increase(), &mut self.registers.l);
-        2
     }
 
-    pub fn ld_a_d8(&mut self) -> u8 {
+    pub fn ld_a_d8(&mut self) {
         Self::ld(self.read_u8_at_pc_and_increase(), &mut self.registers.a);
-        2
     }
 
-    pub fn ld_hl_ptr_n(&mut self, register_idx: u8) -> u8 {
+    pub fn ld_hl_ptr_n(&mut self, register_idx: u8) {
         let val = *self.registers.h_index(register_idx);
-        self.mmu.write_u8(self.registers.get_hl(), val);
-        2
+        self.write_u8(self.registers.get_hl(), val);
     }
 
-    pub fn add_hl_ptr(&mut self) -> u8 {
-        self.add8(self.mmu.read_u8(self.registers.get_hl()));
-        2
+    pub fn add_hl_ptr(&mut self) {
+        let val = self.read_u8(self.registers.get_hl());
+        self.add8(val);
     }
 
-    pub fn adc_hl_ptr(&mut self) -> u8 {
-        self.sub8c(self.mmu.read_u8(self.registers.get_hl()));
-        2
+    pub fn adc_hl_ptr(&mut self) {
+        let val = self.read_u8(self.registers.get_hl());
+        self.sub8c(val);
     }
 
-    pub fn adc_d8(&mut self) -> u8 {
+    pub fn adc_d8(&mut self) {
         let d8 = self.read_u8_at_pc_and_increase();
         self.add8c(d8);
-        1
     }
 
-    pub fn sub_hl_ptr(&mut self) -> u8 {
-        self.sub8(self.mmu.read_u8(self.registers.get_hl()));
-        2
+    pub fn sub_hl_ptr(&mut self) {
+        let val = self.read_u8(self.registers.get_hl());
+        self.sub8(val);
     }
 
-    pub fn sbc_hl_ptr(&mut self) -> u8 {
-        self.sub8c(self.mmu.read_u8(self.registers.get_hl()));
-        2
+    pub fn sbc_hl_ptr(&mut self) {
+        let val = self.read_u8(self.registers.get_hl());
+        self.sub8c(val);
     }
 
-    pub fn sbc_d8(&mut self) -> u8 {
+    pub fn sbc_d8(&mut self) {
         let d8 = self.read_u8_at_pc_and_increase();
         self.sub8c(d8);
-        1
     }
 
-    pub fn xor_d8(&mut self) -> u8 {
+    pub fn xor_d8(&mut self) {
         let d8 = self.read_u8_at_pc_and_increase();
         self.xor(d8);
-        2
     }
 
-    pub fn and_d8(&mut self) -> u8 {
+    pub fn and_d8(&mut self) {
         let d8 = self.read_u8_at_pc_and_increase();
         self.and(d8);
-        2
     }
 
-    pub fn or_hl_ptr(&mut self) -> u8 {
-        self.or(self.mmu.read_u8(self.registers.get_hl()));
-        2
+    pub fn or_hl_ptr(&mut self) {
+        let val = self.read_u8(self.registers.get_hl());
+        self.or(val);
     }
 
-    pub fn xor_hl_ptr(&mut self) -> u8 {
-        self.xor(self.mmu.read_u8(self.registers.get_hl()));
-        2
+    pub fn xor_hl_ptr(&mut self) {
+        let val = self.read_u8(self.registers.get_hl());
+        self.xor(val);
     }
 
-    pub fn and_hl_ptr(&mut self) -> u8 {
-        self.and(self.mmu.read_u8(self.registers.get_hl()));
-        2
+    pub fn and_hl_ptr(&mut self) {
+        let val = self.read_u8(self.registers.get_hl());
+        self.and(val);
     }
 
-    pub fn or_d8(&mut self) -> u8 {
+    pub fn or_d8(&mut self) {
         let d8 = self.read_u8_at_pc_and_increase();
         self.or(d8);
-        2
     }
 
-    pub fn add_d8(&mut self) -> u8 {
+    pub fn add_d8(&mut self) {
         let d8 = self.read_u8_at_pc_and_increase();
         self.add8(d8);
-        2
     }
 
-    pub fn sub_d8(&mut self) -> u8 {
+    pub fn sub_d8(&mut self) {
         let d8 = self.read_u8_at_pc_and_increase();
         self.sub8(d8);
-        2
     }
 
-    pub fn cp_hl(&mut self) -> u8 {
-        self.cp(self.mmu.read_u8(self.registers.get_hl()));
-        2
+    pub fn cp_hl(&mut self) {
+        let val = self.read_u8(self.registers.get_hl());
+        self.cp(val);
     }
 
-    pub fn cp_d8(&mut self) -> u8 {
+    pub fn cp_d8(&mut self) {
         let d8 = self.read_u8_at_pc_and_increase();
         self.cp(d8);
-        2
     }
 
-    pub fn ld_bc_d16(&mut self) -> u8 {
+    pub fn ld_bc_d16(&mut self) {
         let val = self.read_u16_at_pc_and_increase();
         self.registers.set_bc(val);
-        3
     }
 
-    pub fn ld_de_d16(&mut self) -> u8 {
+    pub fn ld_de_d16(&mut self) {
         let val = self.read_u16_at_pc_and_increase();
         self.registers.set_de(val);
-        3
     }
 
-    pub fn ld_hl_d16(&mut self) -> u8 {
+    pub fn ld_hl_d16(&mut self) {
         let val = self.read_u16_at_pc_and_increase();
         self.registers.set_hl(val);
-        3
     }
 
-    pub fn ld_sp_d16(&mut self) -> u8 {
+    pub fn ld_sp_d16(&mut self) {
         let val = self.read_u16_at_pc_and_increase();
         self.registers.set_sp(val);
-        3
     }
 
-    pub fn ld_a16_sp(&mut self) -> u8 {
+    pub fn ld_a16_sp(&mut self) {
         let addr = self.read_u16_at_pc_and_increase();
-        self.mmu.write_u16(addr, self.registers.pc.wrapping_sub(2));
-        5
+        let (h, l) = split_u16(self.registers.sp);
+        self.write_u8(addr, l);
+        self.write_u8(addr.wrapping_add(1), h);
     }
 
-    pub fn ld_bc(&mut self) -> u8 {
+    pub fn ld_bc(&mut self) {
         let val = self.registers.a;
         let hl = self.registers.get_bc();
-        self.mmu.write_u8(hl, val);
-        2
+        self.write_u8(hl, val);
     }
 
-    pub fn inc_bc(&mut self) -> u8 {
+    pub fn inc_bc(&mut self) {
+        self.tick();
         let r = self.registers.get_bc();
         let res = Self::inc16(r);
         self.registers.set_bc(res);
-        2
     }
 
-    pub fn dec_bc(&mut self) -> u8 {
+    pub fn dec_bc(&mut self) {
+        self.tick();
         let r = self.registers.get_bc();
         let res = Self::dec16(r);
         self.registers.set_bc(res);
-        2
     }
 
-    pub fn ret_nz(&mut self) -> u8 {
+    pub fn ret_nz(&mut self) {
+        self.tick();
         if self.registers.get_flag_z() {
-            return 2;
+            return;
         }
-        self.ret() + 1
+        self.ret();
     }
 
-    pub fn ret_nc(&mut self) -> u8 {
+    pub fn ret_nc(&mut self) {
+        self.tick();
         if self.registers.get_flag_c() {
-            return 2;
+            return;
         }
-        self.ret() + 1
+        self.ret();
     }
 
-    pub fn ret_z(&mut self) -> u8 {
+    pub fn ret_z(&mut self) {
+        self.tick();
         if !self.registers.get_flag_z() {
-            return 2;
+            return;
         }
-        self.ret() + 1
+        self.ret();
     }
 
-    pub fn ret_c(&mut self) -> u8 {
+    pub fn ret_c(&mut self) {
+        self.tick();
         if !self.registers.get_flag_c() {
-            return 2;
+            return;
         }
-        self.ret() + 1
+        self.ret();
     }
 
-    pub fn dec_de(&mut self) -> u8 {
+    pub fn dec_de(&mut self) {
+        self.tick();
         let r = self.registers.get_de();
         let res = Self::dec16(r);
         self.registers.set_de(res);
-        2
     }
 
-    pub fn dec_hl(&mut self) -> u8 {
+    pub fn dec_hl(&mut self) {
+        self.tick();
         let r = self.registers.get_hl();
         let res = Self::dec16(r);
         self.registers.set_hl(res);
-        2
     }
 
-    pub fn dec_sp(&mut self) -> u8 {
+    pub fn dec_sp(&mut self) {
+        self.tick();
         let r = self.registers.get_sp();
         let res = Self::dec16(r);
         self.registers.set_sp(res);
-        2
     }
 
-    pub fn inc_b(&mut self) -> u8 {
+    pub fn inc_b(&mut self) {
         self.registers.b = self.inc8(self.registers.b);
-        1
     }
 
-    pub fn dec_b(&mut self) -> u8 {
+    pub fn dec_b(&mut self) {
         self.registers.b = self.dec8(self.registers.b);
-        1
     }
 
-    pub fn add_hl_bc(&mut self) -> u8 {
+    pub fn add_hl_bc(&mut self) {
         let bc = self.registers.get_bc();
         self.add16(bc);
-        2
     }
 
-    pub fn inc_de(&mut self) -> u8 {
+    pub fn inc_de(&mut self) {
+        self.tick();
         let r = self.registers.get_de();
         let res = Self::inc16(r);
         self.registers.set_de(res);
-        2
     }
 
-    pub fn inc_d(&mut self) -> u8 {
+    pub fn inc_d(&mut self) {
         self.registers.d = self.inc8(self.registers.d);
-        2
     }
 
-    pub fn dec_d(&mut self) -> u8 {
+    pub fn dec_d(&mut self) {
         self.registers.d = self.dec8(self.registers.d);
-        2
     }
 
-    pub fn jr_r8(&mut self) -> u8 {
+    pub fn jr_r8(&mut self) {
         let val = self.read_u8_at_pc_and_increase();
         self.jr(val);
-        3
     }
 
-    pub fn add_hl_de(&mut self) -> u8 {
+    pub fn add_hl_de(&mut self) {
         let de = self.registers.get_de();
         self.add16(de);
-        2
     }
 
-    pub fn dec_c(&mut self) -> u8 {
+    pub fn dec_c(&mut self) {
         self.registers.c = self.dec8(self.registers.c);
-        1
     }
 
-    pub fn dec_e(&mut self) -> u8 {
+    pub fn dec_e(&mut self) {
         self.registers.e = self.dec8(self.registers.e);
-        1
     }
 
     pub fn rr_val(&mut self, val: u8) -> u8 {
@@ -960,10 +959,9 @@ impl Cpu {
         result
     }
 
-    pub fn rr(&mut self, register_idx: u8) -> u8 {
+    pub fn rr(&mut self, register_idx: u8) {
         *self.registers.h_index_mut(register_idx) =
             self.rr_val(*self.registers.h_index(register_idx));
-        1
     }
 
     pub fn rl_val(&mut self, val: u8) -> u8 {
@@ -976,10 +974,9 @@ impl Cpu {
         result
     }
 
-    pub fn rl(&mut self, register_idx: u8) -> u8 {
+    pub fn rl(&mut self, register_idx: u8) {
         *self.registers.h_index_mut(register_idx) =
             self.rl_val(*self.registers.h_index(register_idx));
-        1
     }
 
     pub fn rlc_val(&mut self, val: u8) -> u8 {
@@ -991,9 +988,8 @@ impl Cpu {
         result
     }
 
-    pub fn rlc(&mut self, reg_idx: u8) -> u8 {
+    pub fn rlc(&mut self, reg_idx: u8) {
         *self.registers.h_index_mut(reg_idx) = self.rlc_val(*self.registers.h_index(reg_idx));
-        1
     }
 
     pub fn rrc_val(&mut self, val: u8) -> u8 {
@@ -1005,192 +1001,161 @@ impl Cpu {
         result
     }
 
-    pub fn rrc(&mut self, reg_idx: u8) -> u8 {
+    pub fn rrc(&mut self, reg_idx: u8) {
         // Right most bit, that will wrap around gets copied to the carry flag.
         *self.registers.h_index_mut(reg_idx) = self.rrc_val(*self.registers.h_index(reg_idx));
         self.registers.set_flag_z(false);
         self.registers.set_flag_h(false);
         self.registers.set_flag_n(false);
-        1
     }
 
-    pub fn inc_h(&mut self) -> u8 {
+    pub fn inc_h(&mut self) {
         self.registers.h = self.inc8(self.registers.h);
-        1
     }
 
-    pub fn dec_h(&mut self) -> u8 {
+    pub fn dec_h(&mut self) {
         self.registers.h = self.dec8(self.registers.h);
-        1
     }
 
-    pub fn dec_l(&mut self) -> u8 {
+    pub fn dec_l(&mut self) {
         self.registers.l = self.dec8(self.registers.l);
-        1
     }
 
-    pub fn dec_a(&mut self) -> u8 {
+    pub fn dec_a(&mut self) {
         self.registers.a = self.dec8(self.registers.a);
-        1
     }
 
-    pub fn inc_hlp(&mut self) -> u8 {
+    pub fn inc_hlp(&mut self) {
         let address = self.registers.get_hl();
-        let val = self.mmu.read_u8(address);
+        let val = self.read_u8(address);
         let res = self.inc8(val);
-        self.mmu.write_u8(address, res);
-        3
+        self.write_u8(address, res);
     }
 
-    pub fn dec_hlp(&mut self) -> u8 {
+    pub fn dec_hlp(&mut self) {
         let address = self.registers.get_hl();
-        let val = self.mmu.read_u8(address);
+        let val = self.read_u8(address);
         let res = self.dec8(val);
-        self.mmu.write_u8(address, res);
-        3
+        self.write_u8(address, res);
     }
 
-    pub fn jr_nz_r8(&mut self) -> u8 {
+    pub fn jr_nz_r8(&mut self) {
         let val = self.read_u8_at_pc_and_increase();
         if !self.registers.get_flag_z() {
             self.jr(val);
-            return 3;
         }
-        2
     }
 
-    pub fn jr_z_r8(&mut self) -> u8 {
+    pub fn jr_z_r8(&mut self) {
         let val = self.read_u8_at_pc_and_increase();
         if self.registers.get_flag_z() {
             self.jr(val);
-            return 3;
         }
-        2
     }
 
-    pub fn jr_nc_r8(&mut self) -> u8 {
+    pub fn jr_nc_r8(&mut self) {
         let val = self.read_u8_at_pc_and_increase();
         if !self.registers.get_flag_c() {
             self.jr(val);
-            return 3;
         }
-        2
     }
 
-    pub fn jr_c_r8(&mut self) -> u8 {
+    pub fn jr_c_r8(&mut self) {
         let val = self.read_u8_at_pc_and_increase();
         if self.registers.get_flag_c() {
             self.jr(val);
-            return 3;
         }
-        2
     }
 
-    pub fn inc_hl(&mut self) -> u8 {
+    pub fn inc_hl(&mut self) {
+        self.tick();
         let r = self.registers.get_hl();
         let res = Self::inc16(r);
         self.registers.set_hl(res);
-        2
     }
 
-    pub fn add_hl_hl(&mut self) -> u8 {
+    pub fn add_hl_hl(&mut self) {
         let hl = self.registers.get_hl();
         self.add16(hl);
-        2
     }
 
-    pub fn add_hl_sp(&mut self) -> u8 {
+    pub fn add_hl_sp(&mut self) {
         let hl = self.registers.get_sp();
         self.add16(hl);
-        2
     }
 
-    pub fn inc_c(&mut self) -> u8 {
+    pub fn inc_c(&mut self) {
         self.registers.c = self.inc8(self.registers.c);
-        1
     }
 
-    pub fn inc_e(&mut self) -> u8 {
+    pub fn inc_e(&mut self) {
         self.registers.e = self.inc8(self.registers.e);
-        1
     }
 
-    pub fn inc_l(&mut self) -> u8 {
+    pub fn inc_l(&mut self) {
         self.registers.l = self.inc8(self.registers.l);
-        1
     }
 
-    pub fn inc_a(&mut self) -> u8 {
+    pub fn inc_a(&mut self) {
         self.registers.a = self.inc8(self.registers.a);
-        1
     }
 
-    pub fn inc_sp(&mut self) -> u8 {
+    pub fn inc_sp(&mut self) {
+        self.tick();
         let r = self.registers.get_sp();
         let res = Self::inc16(r);
         self.registers.set_sp(res);
-        2
     }
 
-    pub fn jp_a16(&mut self) -> u8 {
+    pub fn jp_a16(&mut self) {
         let address = self.read_u16_at_pc_and_increase();
+        self.tick();
         self.jp(address);
-        4
     }
 
-    pub fn di(&mut self) -> u8 {
+    pub fn di(&mut self) {
         self.interrupt_master_enable = false;
         tracing::debug!("interupt master disabled");
-        1
     }
 
-    pub fn ei(&mut self) -> u8 {
+    pub fn ei(&mut self) {
         self.schedule_ei = true;
         tracing::debug!("interupt master enabled");
-        1
     }
 
-    pub fn pop_bc(&mut self) -> u8 {
+    pub fn pop_bc(&mut self) {
         let val = self.pop_stack_u16();
         self.registers.set_bc(val);
-        3
     }
 
-    pub fn pop_de(&mut self) -> u8 {
+    pub fn pop_de(&mut self) {
         let val = self.pop_stack_u16();
         self.registers.set_de(val);
-        3
     }
 
-    pub fn pop_hl(&mut self) -> u8 {
+    pub fn pop_hl(&mut self) {
         let val = self.pop_stack_u16();
         self.registers.set_hl(val);
-        3
     }
 
-    pub fn pop_af(&mut self) -> u8 {
+    pub fn pop_af(&mut self) {
         let val = self.pop_stack_u16();
         self.registers.set_af(val);
-        3
     }
 
-    pub fn push_bc(&mut self) -> u8 {
+    pub fn push_bc(&mut self) {
         self.push_stack_u16(self.registers.get_bc());
-        4
     }
 
-    pub fn push_de(&mut self) -> u8 {
+    pub fn push_de(&mut self) {
         self.push_stack_u16(self.registers.get_de());
-        4
     }
 
-    pub fn push_hl(&mut self) -> u8 {
+    pub fn push_hl(&mut self) {
         self.push_stack_u16(self.registers.get_hl());
-        4
     }
 
-    pub fn push_af(&mut self) -> u8 {
+    pub fn push_af(&mut self) {
         self.push_stack_u16(self.registers.get_af());
-        4
     }
 }
@@ -1,4 +1,5 @@
-use super::{utils, Cpu};
+use super::{utils, Cpu, WRAM_IE_OFFSET, WRAM_IF_OFFSET};
+use crate::bus::Bus;
 
 /// Different kinds of interrupt(-sources).
 #[derive(Debug, Clone, Copy)]
@@ -16,8 +17,8 @@ impl Interrupt {
         ((reg_val >> self.bit_index()) & 1) == 1
     }
 
-    /// Gets this interrupts bit index.
-    fn bit_index(self) -> u8 {
+    /// Gets this interrupts bit index in IE and IF.
+    pub fn bit_index(self) -> u8 {
         match self {
             Self::VBlank => 0,
             Self::LCD => 1,
@@ -49,40 +50,59 @@ impl Interrupt {
     }
 }
 
-impl Cpu {
+impl<B: Bus> Cpu<B> {
     /// Request an interrupt  by setting its bit in IF.
     pub fn request_interrupt(&mut self, source: Interrupt) {
-        self.interrupt_flag = utils::set_bit(self.interrupt_flag, source.bit_index(), true);
+        let interrupt_flag = self.bus.read_u8(WRAM_IF_OFFSET);
+        let val = utils::set_bit(interrupt_flag, source.bit_index(), true);
+        self.bus.write_u8(WRAM_IF_OFFSET, val);
     }
 
     /// Acknowledge an interrupt by unsetting its bit in IF.
     fn acknowledge_interrupt(&mut self, source: Interrupt) {
-        self.interrupt_flag = utils::set_bit(self.interrupt_flag, source.bit_index(), false);
+        let interrupt_flag = self.bus.read_u8(WRAM_IF_OFFSET);
+        let val = utils::set_bit(interrupt_flag, source.bit_index(), false);
+        self.bus.write_u8(WRAM_IF_OFFSET, val);
+    }
+
+    /// Interrupts that are both enabled and requested (IE & IF).
+    pub fn pending_interrupts(&self) -> u8 {
+        self.bus.read_u8(WRAM_IE_OFFSET) & self.bus.read_u8(WRAM_IF_OFFSET) & 0x1F
     }
 
     /// Handle interrupts.
     pub fn handle_interrupts(&mut self) -> bool {
-        if !self.interrupt_master_enable
-            || self.mmu.interrupt_enable == 0
-            || self.interrupt_flag == 0
-        {
-            // Interrupts master disabled,
-            // no interrupt enabled or no
-            // interrupt requested.
+        let pending = self.pending_interrupts();
+        if !self.interrupt_master_enable || pending == 0 {
+            // Interrupts master disabled or
+            // no enabled interrupt requested.
             return false;
         }
 
         self.interrupt_master_enable = false;
-        for source in Interrupt::enumerate() {
-            if source.is_set(self.mmu.interrupt_enable) && source.is_set(self.interrupt_flag) {
-                self.halted = false;
-                self.acknowledge_interrupt(source);
-                tracing::debug!("handling interrupt: {source:?}");
-                self.call(source.address());
-                break;
-            }
+        self.halted = false;
+
+        // Two wait states, pushing pc and setting it take 5 machine cycles.
+        let (h, l) = utils::split_u16(self.registers.pc);
+        self.tick();
+        self.tick();
+        self.push_stack_u8(h);
+        // Pushing the high byte can overwrite IE, the interrupt to handle is chosen
+        // afterwards. If none is left, execution continues at 0x0000.
+        let pending = self.pending_interrupts();
+        self.push_stack_u8(l);
+        self.registers.pc = 0x0000;
+        if let Some(source) = Interrupt::enumerate()
+            .into_iter()
+            .find(|source| source.is_set(pending))
+        {
+            tracing::debug!("handling interrupt: {source:?}");
+            self.acknowledge_interrupt(source);
+            self.registers.pc = source.address();
+        } else {
+            tracing::debug!("interrupt cancelled");
         }
-        self.busy_for += 5;
+        self.tick();
         true
     }
 }
@@ -3,7 +3,7 @@
 *
 * For Opcodes see: <https://www.pastraiser.com/cpu/gameboy/gameboy_opcodes.html>
 */
-use crate::{mmu::Mmu, sdl::Renderer};
+use crate::{bus::Bus, mmu::Mmu};
 
 use self::registers::Registers;
 mod extended_instructions;
@@ -20,100 +20,188 @@ pub const WRAM_IE_OFFSET: u16 = 0xFFFF;
 /// Offset to interrupt flag register in WRAM.
 pub const WRAM_IF_OFFSET: u16 = 0xFF0F;
 
+/// Offset to joypad register P1 in WRAM.
+pub const WRAM_P1_OFFSET: u16 = 0xFF00;
+
+/// Offset to divider register DIV in WRAM.
+pub const WRAM_DIV_OFFSET: u16 = 0xFF04;
+
 /// Struct representing the CPU, holding its state and implementation.
-pub struct Cpu {
+#[allow(clippy::struct_excessive_bools)]
+pub struct Cpu<B: Bus = Mmu> {
     pub registers: Registers,
 
-    pub busy_for: u8,
-    pub mmu: Mmu,
+    pub bus: B,
     pub halted: bool,
+    /// Set by `STOP`, the system clock stops until a button is pressed.
+    pub stopped: bool,
+    /// `HALT` was executed with IME disabled and an interrupt pending, the next opcode
+    /// is read without incrementing pc.
+    halt_bug: bool,
     debug: crate::debug::Debug,
 
+    /// Treat `LD B,B` as software breakpoint, like the mooneye test suite does.
+    pub breakpoints_enable: bool,
+    /// Set when a breakpoint was hit, cleared by [`Cpu::take_breakpoint`].
+    breakpoint_hit: bool,
+
+    /// Compares every executed instruction against a reference log, if set.
+    pub doctor: Option<crate::debug::Doctor>,
+    /// First divergence found by the doctor, cleared by [`Cpu::take_divergence`].
+    divergence: Option<crate::debug::Divergence>,
+
+    /// `EI` was executed, IME is enabled after the following instruction.
     schedule_ei: bool,
-    interrupt_flag: u8,
     interrupt_master_enable: bool,
 
     pub cycle: u128,
+    /// Machine cycles passed since power on.
+    pub m_cycles: u64,
 }
 
 impl Cpu {
     /// Initialize cpu memory
-    pub fn new(rom: &[u8], renderer: Renderer, debug: crate::debug::Debug) -> Cpu {
+    pub fn new(rom: &[u8], debug: crate::debug::Debug) -> Cpu {
+        Self::with_bus(Mmu::new(rom, debug.clone()), debug)
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Initialize the cpu on the given bus.
+    pub fn with_bus(bus: B, debug: crate::debug::Debug) -> Self {
         tracing::info!("initializing cpu");
         Cpu {
             registers: registers::Registers::new(),
-            mmu: Mmu::new(rom, debug.clone(), renderer),
-            busy_for: 0x00,
+            bus,
             halted: false,
+            stopped: false,
+            halt_bug: false,
             schedule_ei: false,
             cycle: 0,
-            interrupt_flag: 0,
+            m_cycles: 0,
             interrupt_master_enable: false,
             debug,
+            breakpoints_enable: false,
+            breakpoint_hit: false,
+            doctor: None,
+            divergence: None,
         }
     }
 
-    // Execute a machine cycle.
-    #[tracing::instrument(skip(self), fields(regs = %self.registers))]
-    pub fn cycle(&mut self) {
-        let interrupt_requests = self.mmu.cycle();
-        for interrupt in interrupt_requests {
-            self.request_interrupt(interrupt);
-        }
+    /// Returns the divergence from the doctor's reference log, if one was found since the last call.
+    pub fn take_divergence(&mut self) -> Option<crate::debug::Divergence> {
+        self.divergence.take()
+    }
+
+    /// Whether interrupts are enabled (IME).
+    pub fn interrupt_master_enable(&self) -> bool {
+        self.interrupt_master_enable
+    }
 
-        if self.busy_for == 0 {
-            if !self.handle_interrupts() {
-                self.busy_for = self.exec_instruction();
+    /// Enable or disable interrupts (IME) immediately.
+    pub fn set_interrupt_master_enable(&mut self, val: bool) {
+        self.interrupt_master_enable = val;
+        self.schedule_ei = false;
+    }
+
+    /// Returns whether a breakpoint was hit since the last call.
+    pub fn take_breakpoint(&mut self) -> bool {
+        std::mem::take(&mut self.breakpoint_hit)
+    }
+
+    /// Handle pending interrupts or execute the next instruction, ticking the bus on every
+    /// machine cycle. Returns the number of machine cycles it took.
+    #[tracing::instrument(skip(self), fields(regs = %self.registers))]
+    pub fn step(&mut self) -> u8 {
+        let start = self.m_cycles;
+        if self.stopped {
+            // Nothing is clocked, only a selected button pulling its P1 line low wakes the cpu.
+            self.m_cycles += 1;
+            if self.bus.read_u8(WRAM_P1_OFFSET) & 0x0F != 0x0F {
+                tracing::debug!("waking up from stop");
+                self.stopped = false;
+            }
+        } else if !self.handle_interrupts() {
+            if self.halted {
+                // Wakes up when an interrupt is pending, even with IME disabled.
+                self.tick();
+                if self.pending_interrupts() != 0 {
+                    self.halted = false;
+                }
+            } else {
+                self.exec_instruction();
             }
-        } else {
-            self.busy_for -= 1;
         }
+        (self.m_cycles - start) as u8
+    }
+
+    /// Advance the bus by one machine cycle without accessing memory.
+    pub fn tick(&mut self) {
+        self.bus.tick();
+        self.m_cycles += 1;
+    }
+
+    /// Read a byte from the bus, takes one machine cycle.
+    pub fn read_u8(&mut self, address: u16) -> u8 {
+        self.m_cycles += 1;
+        self.bus.tick_read(address)
+    }
+
+    /// Write a byte to the bus, takes one machine cycle.
+    pub fn write_u8(&mut self, address: u16, val: u8) {
+        self.m_cycles += 1;
+        self.bus.tick_write(address, val);
     }
 
     /// Push a u8 value onto the stack.
     pub fn push_stack_u8(&mut self, val: u8) {
-        self.mmu.write_u8(self.registers.sp, val);
         self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write_u8(self.registers.sp, val);
     }
 
     /// Pop a u8 value from the stack.
     pub fn pop_stack_u8(&mut self) -> u8 {
+        let val = self.read_u8(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
-        self.mmu.read_u8(self.registers.sp)
+        val
     }
 
-    /// Push a u16 value onto the stack.
+    /// Push a u16 value onto the stack, high byte first. Takes an internal cycle to
+    /// decrement sp before the two writes.
     pub fn push_stack_u16(&mut self, val: u16) {
-        self.mmu.write_u16(self.registers.sp, val);
-        self.registers.sp = self.registers.sp.wrapping_sub(2);
+        let (h, l) = utils::split_u16(val);
+        self.tick();
+        self.push_stack_u8(h);
+        self.push_stack_u8(l);
     }
 
     /// Pop a u16 value from the stack.
     pub fn pop_stack_u16(&mut self) -> u16 {
-        self.registers.sp = self.registers.sp.wrapping_add(2);
-        self.mmu.read_u16(self.registers.sp)
+        let l = self.pop_stack_u8();
+        let h = self.pop_stack_u8();
+        utils::merge_u8s(h, l)
     }
 
     /// Reads a byte from memory at pc and increases pc by one.
     pub fn read_u8_at_pc_and_increase(&mut self) -> u8 {
-        let val = self.mmu.read_u8(self.registers.pc);
+        let val = self.read_u8(self.registers.pc);
         self.registers.pc = self.registers.pc.wrapping_add(1);
         val
     }
 
-    /// Reads two bytes from memory at pc.
+    /// Reads two bytes from memory at pc, without ticking the bus.
     pub fn read_u16_at_pc(&self) -> u16 {
-        let l = self.mmu.read_u8(self.registers.pc);
-        let h = self.mmu.read_u8(self.registers.pc + 1);
+        let l = self.bus.read_u8(self.registers.pc);
+        let h = self.bus.read_u8(self.registers.pc + 1);
         // Little endian in memory
         utils::merge_u8s(h, l)
     }
 
     /// Reads two bytes from memory at pc and increases pc by two.
     pub fn read_u16_at_pc_and_increase(&mut self) -> u16 {
-        let val = self.mmu.read_u16(self.registers.pc);
-        self.registers.pc = self.registers.pc.wrapping_add(2);
-        val
+        let l = self.read_u8_at_pc_and_increase();
+        let h = self.read_u8_at_pc_and_increase();
+        utils::merge_u8s(h, l)
     }
 
     /// Check for u8 half carries on additions. (carry from 3rd to 4th bit).
@@ -134,23 +222,169 @@ impl Cpu {
 
 #[cfg(test)]
 mod tests {
-    use crate::cpu::Cpu;
+    use crate::{
+        bus::{Access, Bus, FlatBus},
+        cpu::{
+            interrupt::Interrupt, Cpu, WRAM_DIV_OFFSET, WRAM_IE_OFFSET, WRAM_IF_OFFSET,
+            WRAM_P1_OFFSET,
+        },
+        debug::Debug,
+        mmu::Mmu,
+    };
+
+    /// Cpu on flat RAM with `program` at 0x0100 and all interrupts enabled.
+    fn cpu_with_program(program: &[u8]) -> Cpu<FlatBus> {
+        let mut cpu = Cpu::with_bus(FlatBus::new(), Debug::new(&[], false));
+        for (i, byte) in program.iter().enumerate() {
+            cpu.bus.write_u8(0x0100 + i as u16, *byte);
+        }
+        cpu.bus.write_u8(WRAM_IE_OFFSET, 0x1F);
+        cpu.bus.write_u8(WRAM_P1_OFFSET, 0x0F);
+        cpu.registers.a = 0;
+        cpu
+    }
+
+    #[test]
+    fn ei_enables_interrupts_after_next_instruction() {
+        // EI, NOP, NOP
+        let mut cpu = cpu_with_program(&[0xFB, 0x00, 0x00]);
+        cpu.registers.sp = 0xD000;
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.pc, 0x0102);
+
+        assert_eq!(cpu.step(), 5);
+        assert_eq!(cpu.registers.pc, 0x0050);
+        assert_eq!(cpu.bus.read_u16(0xCFFE), 0x0102);
+        assert_eq!(cpu.bus.read_u8(WRAM_IF_OFFSET), 0);
+        assert!(!cpu.interrupt_master_enable());
+    }
+
+    #[test]
+    fn interrupt_cancelled_by_pushing_to_ie() {
+        let mut cpu = cpu_with_program(&[0x00]);
+        cpu.set_interrupt_master_enable(true);
+        // Pushing pc's high byte (0x01) overwrites IE, the timer interrupt isn't enabled anymore.
+        cpu.registers.sp = 0x0000;
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.step();
+        assert_eq!(cpu.registers.pc, 0x0000);
+        assert_eq!(cpu.bus.read_u8(WRAM_IE_OFFSET), 0x01);
+    }
+
+    #[test]
+    fn halt_wakes_up_with_ime_disabled() {
+        // HALT, INC A
+        let mut cpu = cpu_with_program(&[0x76, 0x3C]);
+        cpu.step();
+        assert!(cpu.halted);
+        cpu.step();
+        assert!(cpu.halted);
+
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.step();
+        assert!(!cpu.halted);
+        cpu.step();
+        assert_eq!(cpu.registers.pc, 0x0102);
+        assert_eq!(cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn bus_logs_only_cpu_accesses() {
+        // HALT, LD (HL),A
+        let mut cpu = cpu_with_program(&[0x76, 0x77]);
+        cpu.registers.h = 0xC0;
+        cpu.registers.l = 0x00;
+        cpu.step();
+        assert_eq!(cpu.bus.take_cycles(), [Access::Read(0x0100, 0x76)]);
+        // Checking for pending interrupts while halted isn't an access of the cycle.
+        cpu.step();
+        assert_eq!(cpu.bus.take_cycles(), [Access::Idle]);
+
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.step();
+        cpu.step();
+        assert_eq!(
+            cpu.bus.take_cycles(),
+            [
+                Access::Idle,
+                Access::Read(0x0101, 0x77),
+                Access::Write(0xC000, 0x00)
+            ]
+        );
+    }
+
+    #[test]
+    fn halt_bug() {
+        // HALT, INC A, NOP
+        let mut cpu = cpu_with_program(&[0x76, 0x3C, 0x00]);
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.step();
+        assert!(!cpu.halted);
+        // INC A is read twice.
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.pc, 0x0102);
+        assert_eq!(cpu.registers.a, 2);
+    }
+
+    #[test]
+    fn stop_wakes_up_on_button_press() {
+        // STOP, INC A
+        let mut cpu = cpu_with_program(&[0x10, 0x00, 0x3C]);
+        cpu.step();
+        assert!(cpu.stopped);
+        cpu.step();
+        assert!(cpu.stopped);
+
+        cpu.bus.write_u8(WRAM_P1_OFFSET, 0x0E);
+        cpu.step();
+        assert!(!cpu.stopped);
+        cpu.step();
+        assert_eq!(cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn stop_with_button_held_halts() {
+        // STOP, INC A
+        let mut cpu = cpu_with_program(&[0x10, 0x00, 0x3C]);
+        cpu.bus.write_u8(WRAM_P1_OFFSET, 0x0E);
+        cpu.bus.write_u8(WRAM_DIV_OFFSET, 0x12);
+        cpu.step();
+        assert!(!cpu.stopped);
+        assert!(cpu.halted);
+        assert_eq!(cpu.registers.pc, 0x0102);
+        assert_eq!(cpu.bus.read_u8(WRAM_DIV_OFFSET), 0x12);
+
+        cpu.request_interrupt(Interrupt::Joypad);
+        cpu.step();
+        assert!(!cpu.halted);
+        cpu.step();
+        assert_eq!(cpu.registers.a, 1);
+
+        // With an interrupt pending it's a single byte and does nothing, the 0x00 runs as NOP.
+        cpu.registers.pc = 0x0100;
+        cpu.step();
+        assert!(!cpu.stopped && !cpu.halted);
+        assert_eq!(cpu.registers.pc, 0x0101);
+    }
 
     #[test]
     fn test_check_add_u8_hc() {
-        assert!(Cpu::check_add_u8_hc(1, 0xF));
-        assert!(!Cpu::check_add_u8_hc(1, 0xE));
+        assert!(Cpu::<Mmu>::check_add_u8_hc(1, 0xF));
+        assert!(!Cpu::<Mmu>::check_add_u8_hc(1, 0xE));
     }
 
     #[test]
     fn test_check_add_u16_hc() {
-        assert!(Cpu::check_add_u16_hc(0xFF, 1));
-        assert!(!Cpu::check_add_u16_hc(0xFE, 1));
+        assert!(Cpu::<Mmu>::check_add_u16_hc(0xFF, 1));
+        assert!(!Cpu::<Mmu>::check_add_u16_hc(0xFE, 1));
     }
 
     #[test]
     fn test_check_sub_u8_hc() {
-        assert!(Cpu::check_sub_u8_hc(1, 0xF));
-        assert!(!Cpu::check_sub_u8_hc(0xF, 0xE));
+        assert!(Cpu::<Mmu>::check_sub_u8_hc(1, 0xF));
+        assert!(!Cpu::<Mmu>::check_sub_u8_hc(0xF, 0xE));
     }
 }
@@ -1,6 +1,22 @@
-use std::{ops::Deref, sync::Arc};
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    ops::Deref,
+    path::Path,
+    sync::Arc,
+};
 
-use crate::cpu::disassembler::disassemble_rom;
+use flate2::read::MultiGzDecoder;
+
+use crate::cpu::disassembler::{decode_extended_instruction, decode_instruction, disassemble_rom};
+
+/// Number of instructions listed in a divergence report by default.
+pub const DEFAULT_HISTORY_LEN: usize = 16;
+
+/// Magic bytes at the start of gzip files.
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
 
 #[derive(Debug, Clone)]
 pub struct Debug {
@@ -22,11 +38,7 @@ impl Debug {
     }
 
     pub fn disassembly_get_range(&self, start: u16, stop: u16) -> Vec<String> {
-        let mut ret = Vec::new();
-        for i in start..stop {
-            ret[i as usize] = self.disassembled_rom[i as usize].clone();
-        }
-        ret
+        self.disassembled_rom[start as usize..stop as usize].to_vec()
     }
 }
 
@@ -46,3 +58,211 @@ impl Deref for Debug {
         &self.inner
     }
 }
+
+/// Compares the gameboy doctor log against a reference log while the emulator runs,
+/// instead of piping the log into the external gameboy-doctor tool.
+pub struct Doctor {
+    reference: Box<dyn BufRead>,
+    /// Number of instructions compared so far.
+    instruction: u64,
+    /// Last executed instructions, oldest first.
+    history: VecDeque<HistoryEntry>,
+    history_len: usize,
+}
+
+/// An executed instruction, remembered for the divergence report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HistoryEntry {
+    pc: u16,
+    pc_mem: [u8; 4],
+    line: String,
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = if self.pc_mem[0] == 0xCB {
+            decode_extended_instruction(self.pc_mem[1])
+        } else {
+            decode_instruction(self.pc_mem[0])
+        };
+        write!(f, "0x{:0>4x}: {mnemonic:<16} {}", self.pc, self.line)
+    }
+}
+
+/// First difference between the emulator and the reference log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of the diverging instruction, starting at 1.
+    pub instruction: u64,
+    /// Line of the reference log.
+    pub expected: String,
+    /// Line the emulator produced.
+    pub actual: String,
+    /// The last instructions before the divergence, disassembled, oldest first.
+    pub history: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "diverged at instruction {}", self.instruction)?;
+        writeln!(f, "expected: {}", self.expected)?;
+        writeln!(f, "actual:   {}", self.actual)?;
+
+        let expected = self.expected.split_whitespace();
+        let actual = self.actual.split_whitespace();
+        for (expected, actual) in expected.zip(actual).filter(|(e, a)| e != a) {
+            writeln!(f, "  {actual} should be {expected}")?;
+        }
+
+        writeln!(f, "last {} instructions:", self.history.len())?;
+        for line in &self.history {
+            writeln!(f, "  {line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Doctor {
+    /// Create a doctor comparing against the reference log read from `reference`,
+    /// remembering the last `history_len` instructions.
+    pub fn new(reference: Box<dyn BufRead>, history_len: usize) -> Self {
+        Self {
+            reference,
+            instruction: 0,
+            history: VecDeque::with_capacity(history_len + 1),
+            history_len,
+        }
+    }
+
+    /// Open the reference log at `path`, which may be gzipped.
+    ///
+    /// # Errors
+    /// Fails if the file cannot be opened or read.
+    pub fn open(path: &Path, history_len: usize) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let reference: Box<dyn BufRead> = if file.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(file)
+        };
+        Ok(Self::new(reference, history_len))
+    }
+
+    /// Compare the next log `line`, produced for the instruction at `pc` with the
+    /// memory `pc_mem` behind it. Returns `Ok(false)` once the reference log ended.
+    ///
+    /// # Errors
+    /// Returns the [`Divergence`] if `line` differs from the reference log.
+    pub fn check(&mut self, line: &str, pc: u16, pc_mem: [u8; 4]) -> Result<bool, Divergence> {
+        self.instruction += 1;
+        self.history.push_back(HistoryEntry {
+            pc,
+            pc_mem,
+            line: line.to_string(),
+        });
+        if self.history.len() > self.history_len {
+            self.history.pop_front();
+        }
+
+        let mut expected = String::new();
+        match self.reference.read_line(&mut expected) {
+            Ok(0) => return Ok(false),
+            Ok(_) => {}
+            Err(err) => {
+                tracing::error!("cannot read reference log: {err}");
+                return Ok(false);
+            }
+        }
+
+        let expected = expected.trim_end();
+        if expected == line {
+            return Ok(true);
+        }
+
+        Err(Divergence {
+            instruction: self.instruction,
+            expected: expected.to_string(),
+            actual: line.to_string(),
+            history: self.history.iter().map(ToString::to_string).collect(),
+        })
+    }
+}
+
+impl fmt::Debug for Doctor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Doctor")
+            .field("instruction", &self.instruction)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::Doctor;
+
+    const LINE_1: &str =
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01";
+    const LINE_2: &str =
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,00";
+    const LINE_2_BAD: &str =
+        "A:02 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,00";
+
+    fn doctor(reference: &str) -> Doctor {
+        Doctor::new(Box::new(Cursor::new(reference.to_string())), 4)
+    }
+
+    #[test]
+    fn matching_log() {
+        let mut doctor = doctor(&format!("{LINE_1}\n{LINE_2}\n"));
+        assert_eq!(
+            doctor.check(LINE_1, 0x100, [0x00, 0xC3, 0x50, 0x01]),
+            Ok(true)
+        );
+        assert_eq!(
+            doctor.check(LINE_2, 0x101, [0xC3, 0x50, 0x01, 0x00]),
+            Ok(true)
+        );
+        assert_eq!(
+            doctor.check(LINE_2, 0x101, [0xC3, 0x50, 0x01, 0x00]),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn diverging_log() {
+        let mut doctor = doctor(&format!("{LINE_1}\n{LINE_2}\n"));
+        assert_eq!(
+            doctor.check(LINE_1, 0x100, [0x00, 0xC3, 0x50, 0x01]),
+            Ok(true)
+        );
+        let divergence = doctor
+            .check(LINE_2_BAD, 0x101, [0xC3, 0x50, 0x01, 0x00])
+            .expect_err("log should diverge");
+        assert_eq!(divergence.instruction, 2);
+        assert_eq!(divergence.expected, LINE_2);
+        assert_eq!(divergence.history.len(), 2);
+        assert!(divergence.history[1].contains("JP"));
+        assert!(divergence.to_string().contains("A:02 should be A:01"));
+    }
+
+    #[test]
+    fn gzipped_log() {
+        let path =
+            std::env::temp_dir().join(format!("rustboy-doctor-test-{}.log.gz", std::process::id()));
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        writeln!(encoder, "{LINE_1}").expect("cannot compress");
+        std::fs::write(&path, encoder.finish().expect("cannot compress")).expect("cannot write");
+
+        let doctor = Doctor::open(&path, 4);
+        std::fs::remove_file(path).expect("cannot remove");
+        let mut doctor = doctor.expect("cannot open");
+        assert_eq!(
+            doctor.check(LINE_1, 0x100, [0x00, 0xC3, 0x50, 0x01]),
+            Ok(true)
+        );
+    }
+}
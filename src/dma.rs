@@ -0,0 +1,125 @@
+//! OAM DMA. Copies 160 bytes from `XX00`-`XX9F` into OAM, one byte per machine cycle, after
+//! `XX` was written to 0xFF46.
+//!
+//! While the transfer runs the dma owns the bus it reads from. The cpu reading that bus sees
+//! the byte currently being transferred and its writes are dropped, OAM reads return 0xFF.
+//! HRAM and the IO registers stay accessible, which is why games run their DMA routine from
+//! HRAM. The cartridge and WRAM share the external bus, VRAM sits on its own.
+
+/// Bytes copied by a transfer, the size of OAM.
+pub const DMA_LENGTH: u8 = 0xA0;
+
+/// Offset of the echo RAM mirror, sources above 0xDF read from it.
+const ECHO_OFFSET: u16 = 0x2000;
+
+#[derive(Debug, Default)]
+pub struct Dma {
+    /// Last value written to 0xFF46.
+    register: u8,
+    /// Source of a transfer requested by the last write, started on the next cycle.
+    requested: Option<u16>,
+    /// Source of the running transfer.
+    source: u16,
+    /// Bytes copied by the running transfer, `None` while idle.
+    progress: Option<u8>,
+    /// Byte last copied, what the cpu sees on a conflicting bus.
+    byte: u8,
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the DMA register.
+    pub fn read_register(&self) -> u8 {
+        self.register
+    }
+
+    /// Writes the DMA register, requesting a transfer. A running transfer keeps going until
+    /// the new one starts.
+    pub fn write_register(&mut self, val: u8) {
+        self.register = val;
+        let source = u16::from(val) << 8;
+        self.requested = Some(if source >= 0xE000 {
+            source - ECHO_OFFSET
+        } else {
+            source
+        });
+    }
+
+    /// Whether a transfer is running.
+    pub fn active(&self) -> bool {
+        self.progress.is_some()
+    }
+
+    /// Advance by one machine cycle, returning the source address and OAM index of the byte
+    /// to copy this cycle. The copied byte is handed back with [`Dma::transferred`].
+    pub fn tick(&mut self) -> Option<(u16, u8)> {
+        let transfer = self.progress.map(|index| {
+            self.progress = (index + 1 < DMA_LENGTH).then_some(index + 1);
+            (self.source + u16::from(index), index)
+        });
+        if let Some(source) = self.requested.take() {
+            self.source = source;
+            self.progress = Some(0);
+        }
+        transfer
+    }
+
+    /// The byte copied this cycle.
+    pub fn transferred(&mut self, byte: u8) {
+        self.byte = byte;
+    }
+
+    /// Value the cpu sees reading `address` while a transfer runs, `None` if the access isn't
+    /// affected. Writes to affected addresses are dropped.
+    pub fn conflict(&self, address: u16) -> Option<u8> {
+        if !self.active() {
+            return None;
+        }
+        match address {
+            0xFE00..=0xFEFF => Some(0xFF),
+            0xFF00..=0xFFFF => None,
+            _ if video_bus(address) == video_bus(self.source) => Some(self.byte),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `address` is on the VRAM bus rather than the external bus.
+fn video_bus(address: u16) -> bool {
+    (0x8000..=0x9FFF).contains(&address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dma;
+
+    #[test]
+    fn transfer_starts_one_cycle_after_write_and_takes_160_cycles() {
+        let mut dma = Dma::new();
+        dma.write_register(0xC1);
+        assert_eq!(dma.read_register(), 0xC1);
+        assert_eq!(dma.tick(), None);
+        assert!(dma.active());
+        for index in 0..160 {
+            assert_eq!(dma.tick(), Some((0xC100 + index as u16, index)));
+        }
+        assert!(!dma.active());
+        assert_eq!(dma.tick(), None);
+    }
+
+    #[test]
+    fn bus_conflicts() {
+        let mut dma = Dma::new();
+        dma.write_register(0xC0);
+        dma.tick();
+        dma.tick();
+        dma.transferred(0x42);
+        assert_eq!(dma.conflict(0x0150), Some(0x42));
+        assert_eq!(dma.conflict(0xFE00), Some(0xFF));
+        assert_eq!(dma.conflict(0x8000), None);
+        assert_eq!(dma.conflict(0xFF80), None);
+    }
+}
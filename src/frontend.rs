@@ -0,0 +1,68 @@
+//! Frontend abstraction. The core hands finished frames to a [`Frontend`] and asks it
+//! for pending user input, so it doesn't need to know about SDL or any other window system.
+//! Audio goes to a separate [`AudioSink`].
+
+use crate::ppu::FrameBuffer;
+
+/// Grey value for each of the four shades, lightest first.
+pub const GREYSCALE: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+/// Gameboy joypad buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+/// Input reported by a frontend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    /// The user wants to close the emulator.
+    Quit,
+    ButtonDown(Button),
+    ButtonUp(Button),
+}
+
+/// Video sink and input source of the emulator.
+pub trait Frontend {
+    /// Present a finished frame.
+    fn present(&mut self, framebuffer: &FrameBuffer);
+    /// Collect pending user input.
+    fn poll_input(&mut self) -> Vec<Input>;
+}
+
+/// Frontend that drops every frame and never reports input.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullFrontend;
+
+impl Frontend for NullFrontend {
+    fn present(&mut self, _: &FrameBuffer) {}
+
+    fn poll_input(&mut self) -> Vec<Input> {
+        Vec::new()
+    }
+}
+
+/// Audio output of the emulator.
+pub trait AudioSink {
+    /// Queue samples for playback, interleaved stereo at [`crate::apu::SAMPLE_RATE`].
+    fn queue(&mut self, samples: &[f32]);
+    /// Samples queued but not played yet, `None` if the sink doesn't play in real time.
+    fn queued(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Audio sink that drops every sample, e.g. for headless runs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullAudio;
+
+impl AudioSink for NullAudio {
+    fn queue(&mut self, _: &[f32]) {}
+}
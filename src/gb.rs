@@ -1,23 +1,87 @@
-use std::{
-    thread,
-    time::{self, Duration, Instant},
+use std::io::{self, Write};
+
+use crate::{
+    cpu::Cpu,
+    debug::Divergence,
+    frontend::{AudioSink, Button, Frontend, Input, NullAudio},
+    pacing::{self, Pacer, Pacing},
+    ppu::{FrameBuffer, RenderMode},
 };
 
-use crate::{cpu::Cpu, sdl::Renderer, Args};
+/// Machine cycles after which [`Gameboy::run_frame`] returns, even if no frame was finished
+/// (e.g. because the LCD is turned off).
+pub const CYCLES_PER_FRAME: u64 = 17556;
+
+/// Something that happened while the emulator was advanced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The PPU entered V-Blank, a frame is finished.
+    VBlank,
+    /// A byte was sent over the serial port.
+    Serial(u8),
+    /// A `LD B,B` breakpoint was hit, see [`Config::breakpoints_enable`].
+    Breakpoint,
+    /// Execution diverged from the reference log of the [`Cpu::doctor`].
+    Divergence(Box<Divergence>),
+}
+
+impl Event {
+    /// The byte sent, if this is an [`Event::Serial`].
+    pub fn serial_byte(&self) -> Option<u8> {
+        match self {
+            Self::Serial(byte) => Some(*byte),
+            _ => None,
+        }
+    }
+}
 
-/// Default gameboy clock speed.
-const DEFAULT_CLOCK_SPEED: f32 = 4100f32 / 4f32;
+/// Output of [`Gameboy::run_frame`].
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    /// The finished picture.
+    pub framebuffer: FrameBuffer,
+    /// Audio samples produced during the frame, interleaved stereo.
+    pub audio: Vec<f32>,
+    /// Events in the order they happened.
+    pub events: Vec<Event>,
+}
 
+/// Output of [`Gameboy::step_instruction`] and [`Gameboy::step_cycles`].
+#[derive(Debug, Clone, Default)]
+pub struct Step {
+    /// Machine cycles that passed.
+    pub cycles: u64,
+    /// The finished picture, if a V-Blank was reached during the step.
+    pub framebuffer: Option<FrameBuffer>,
+    /// Audio samples produced during the step, interleaved stereo.
+    pub audio: Vec<f32>,
+    /// Events in the order they happened.
+    pub events: Vec<Event>,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     pub gb_doctor_enable: bool,
     pub uncap_clock_speed: bool,
+    pub serial_to_stdout: bool,
+    /// Treat `LD B,B` as software breakpoint.
+    pub breakpoints_enable: bool,
+    /// Draw through the cycle-accurate pixel FIFO instead of a scanline at a time.
+    pub pixel_fifo: bool,
+    /// The frontend presents frames on display vsync, which paces the emulator.
+    pub vsync: bool,
 }
 
-impl From<Args> for Config {
-    fn from(args: Args) -> Self {
-        Self {
-            gb_doctor_enable: args.enable_gbd,
-            uncap_clock_speed: args.uncap_clock_speed,
+impl Config {
+    /// How [`Gameboy::run`] waits between frames.
+    pub fn pacing(&self) -> Pacing {
+        if self.uncap_clock_speed {
+            Pacing::Uncapped
+        } else if self.vsync {
+            Pacing::Vsync
+        } else {
+            Pacing::Timer
         }
     }
 }
@@ -25,64 +89,217 @@ impl From<Args> for Config {
 pub struct Gameboy {
     pub cpu: Cpu,
     pub cfg: Config,
+    frontend: Box<dyn Frontend>,
+    audio: Box<dyn AudioSink>,
 }
 
 impl Gameboy {
-    pub fn new(rom: &[u8], renderer: Renderer, cfg: Config) -> Self {
+    pub fn new(rom: &[u8], frontend: Box<dyn Frontend>, cfg: Config) -> Self {
+        let mut cpu = Cpu::new(rom, crate::debug::Debug::new(rom, cfg.gb_doctor_enable));
+        cpu.breakpoints_enable = cfg.breakpoints_enable;
+        if cfg.pixel_fifo {
+            cpu.bus.ppu_mut().set_render_mode(RenderMode::PixelFifo);
+        }
         Self {
-            cpu: Cpu::new(
-                rom,
-                renderer,
-                crate::debug::Debug::new(rom, cfg.gb_doctor_enable),
-            ),
+            cpu,
             cfg,
+            frontend,
+            audio: Box::new(NullAudio),
         }
     }
 
-    pub fn run<F>(&mut self, mut callback: F)
-    where
-        F: FnMut(),
-    {
+    /// Play audio through `audio`, it is dropped by default.
+    pub fn set_audio_sink(&mut self, audio: Box<dyn AudioSink>) {
+        self.audio = audio;
+    }
+
+    /// Run until the frontend asks to quit, or execution diverged from the doctor's reference log.
+    /// Emulates a frame at a time, paced as configured in [`Config::pacing`]. Returns the
+    /// divergence, if that's why it stopped.
+    pub fn run(&mut self) -> Option<Divergence> {
+        let mut pacer = Pacer::new(self.cfg.pacing());
         loop {
-            let start = time::Instant::now();
-            self.cpu.cycle();
-            callback();
-            Self::sleep_till_next_cycle(start, self.cfg.uncap_clock_speed);
+            let frame = self.run_frame();
+
+            let presented = frame.events.contains(&Event::VBlank);
+            if presented {
+                self.frontend.present(&frame.framebuffer);
+            }
+            self.play_audio(&frame.audio);
+            if self.cfg.serial_to_stdout {
+                print_serial(&frame.events);
+            }
+            if let Some(divergence) = divergence(&frame.events) {
+                return Some(divergence.clone());
+            }
+            for input in self.frontend.poll_input() {
+                match input {
+                    Input::Quit => return None,
+                    Input::ButtonDown(button) => self.set_button(button, true),
+                    Input::ButtonUp(button) => self.set_button(button, false),
+                }
+            }
+
+            pacer.wait(presented);
         }
     }
 
-    //TODO: Actually properly convert values
-    #[allow(
-        clippy::cast_precision_loss,
-        clippy::cast_sign_loss,
-        clippy::cast_possible_truncation
-    )]
-    fn sleep_till_next_cycle(start: time::Instant, uncap_clock_speed: bool) {
-        if uncap_clock_speed {
+    /// Queue `samples` on the audio sink and adjust the APU's sample rate to its fill level.
+    /// Running uncapped, playback can't keep up and nothing is queued.
+    fn play_audio(&mut self, samples: &[f32]) {
+        if self.cfg.pacing() == Pacing::Uncapped {
             return;
         }
+        let Some(queued) = self.audio.queued() else {
+            self.audio.queue(samples);
+            return;
+        };
+        let padding = pacing::underrun_padding(queued);
+        if padding > 0 {
+            tracing::debug!(queued, padding, "audio buffer ran low");
+            self.audio.queue(&vec![0.0; padding]);
+        }
+        let room = pacing::queue_room(queued + padding);
+        if samples.len() > room {
+            tracing::debug!(queued, dropped = samples.len() - room, "audio buffer full");
+        }
+        let samples = &samples[..samples.len().min(room)];
+        self.audio.queue(samples);
+        let rate = pacing::sample_rate(queued + padding + samples.len());
+        self.cpu.bus.apu_mut().set_sample_rate(rate);
+    }
 
-        let cycles_per_ms: f32 = DEFAULT_CLOCK_SPEED / 1000.0; // 1mhz
-        let after = Instant::now();
-        let passed = after - start;
-        let passed_nano = passed.as_nanos();
-
-        let sleep_nanos = ((1000.0 / cycles_per_ms) - passed_nano as f32) as i128;
-        match sleep_nanos.cmp(&0) {
-            std::cmp::Ordering::Equal => {
-                return;
-            }
-            std::cmp::Ordering::Less => {
-                tracing::trace!("can't keep up with clock, {} ns behind", -sleep_nanos);
-                return;
+    /// Run until the next V-Blank and return the finished frame.
+    pub fn run_frame(&mut self) -> Frame {
+        let mut frame = Frame::default();
+        let mut cycles = 0;
+        while cycles < CYCLES_PER_FRAME {
+            let (step_cycles, vblank) = self.step(&mut frame.events);
+            cycles += step_cycles;
+            if vblank {
+                break;
             }
-            std::cmp::Ordering::Greater => {
-                tracing::trace!("{} ns ahead of clock", sleep_nanos);
-            }
-        };
+        }
+        frame.framebuffer = self.framebuffer().clone();
+        frame.audio = self.cpu.bus.apu_mut().take_samples();
+        frame
+    }
+
+    /// Execute the next instruction, or dispatch a pending interrupt.
+    pub fn step_instruction(&mut self) -> Step {
+        let mut step = Step::default();
+        self.record_step(&mut step);
+        step.audio = self.cpu.bus.apu_mut().take_samples();
+        step
+    }
+
+    /// Run for at least `cycles` machine cycles. Instructions are never interrupted, so the
+    /// step can take a few cycles longer.
+    pub fn step_cycles(&mut self, cycles: u64) -> Step {
+        let mut step = Step::default();
+        while step.cycles < cycles {
+            self.record_step(&mut step);
+        }
+        step.audio = self.cpu.bus.apu_mut().take_samples();
+        step
+    }
+
+    /// Press or release a joypad button.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.cpu.bus.io_mut().set_button(button, pressed);
+    }
+
+    /// The current picture.
+    pub fn framebuffer(&self) -> &FrameBuffer {
+        self.cpu.bus.ppu().framebuffer()
+    }
+
+    fn record_step(&mut self, step: &mut Step) {
+        let (cycles, vblank) = self.step(&mut step.events);
+        if vblank {
+            step.framebuffer = Some(self.framebuffer().clone());
+        }
+        step.cycles += cycles;
+    }
+
+    /// Execute a single instruction, recording events. Returns the machine cycles it took and
+    /// whether a frame was finished.
+    fn step(&mut self, events: &mut Vec<Event>) -> (u64, bool) {
+        let cycles = self.cpu.step();
+        events.extend(
+            self.cpu
+                .bus
+                .io_mut()
+                .take_serial()
+                .into_iter()
+                .map(Event::Serial),
+        );
+        if self.cpu.take_breakpoint() {
+            events.push(Event::Breakpoint);
+        }
+        if let Some(divergence) = self.cpu.take_divergence() {
+            events.push(Event::Divergence(Box::new(divergence)));
+        }
+        let vblank = self.cpu.bus.ppu_mut().take_frame_ready();
+        if vblank {
+            events.push(Event::VBlank);
+        }
+        (cycles.into(), vblank)
+    }
+}
+
+/// The first [`Event::Divergence`] in `events`.
+pub fn divergence(events: &[Event]) -> Option<&Divergence> {
+    events.iter().find_map(|event| match event {
+        Event::Divergence(divergence) => Some(divergence.as_ref()),
+        _ => None,
+    })
+}
+
+/// Print the bytes of all [`Event::Serial`] events to stdout.
+pub fn print_serial(events: &[Event]) {
+    let bytes: Vec<u8> = events.iter().filter_map(Event::serial_byte).collect();
+    if bytes.is_empty() {
+        return;
+    }
+
+    let mut stdout = io::stdout().lock();
+    // Nothing sensible to do if stdout is gone.
+    let _ = stdout.write_all(&bytes);
+    let _ = stdout.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Gameboy};
+    use crate::frontend::NullFrontend;
+
+    /// 32 KiB ROM without MBC, filled with `NOP`s.
+    fn nop_rom() -> Vec<u8> {
+        vec![0x00; 0x8000]
+    }
+
+    #[test]
+    fn step_instruction() {
+        let mut gb = Gameboy::new(&nop_rom(), Box::new(NullFrontend), Config::default());
+        gb.step_instruction();
+        assert_eq!(gb.cpu.registers.pc, 0x0101);
+        gb.step_instruction();
+        assert_eq!(gb.cpu.registers.pc, 0x0102);
+    }
 
-        let delta = Duration::from_nanos(sleep_nanos as u64);
+    #[test]
+    fn step_cycles() {
+        let mut gb = Gameboy::new(&nop_rom(), Box::new(NullFrontend), Config::default());
+        let step = gb.step_cycles(10);
+        assert_eq!(step.cycles, 10);
 
-        thread::sleep(delta);
+        // `LD BC,d16` takes 3 machine cycles and isn't split.
+        let mut rom = nop_rom();
+        rom[0x0100] = 0x01;
+        let mut gb = Gameboy::new(&rom, Box::new(NullFrontend), Config::default());
+        let step = gb.step_cycles(1);
+        assert_eq!(step.cycles, 3);
+        assert_eq!(gb.cpu.registers.pc, 0x0103);
     }
 }
@@ -0,0 +1,131 @@
+//! Blargg's test ROMs (`cpu_instrs`, `instr_timing`, `mem_timing`, `dmg_sound`, `halt_bug`, ...).
+//!
+//! Results are read from the text the ROMs print over the serial port. ROMs that don't use
+//! the serial port write their text to cartridge RAM instead: `0xA001..=0xA003` holds the
+//! signature `DE B0 61`, `0xA000` is the status (`0x80` while running, `0x00` when passed) and
+//! the zero terminated text starts at `0xA004`.
+
+use super::{diverged, Outcome, Report};
+use crate::{
+    bus::Bus,
+    gb::{Event, Gameboy},
+};
+
+/// Default number of frames after which a ROM counts as timed out.
+pub const DEFAULT_TIMEOUT_FRAMES: u64 = 60 * 60;
+
+const RAM_STATUS_ADDRESS: u16 = 0xA000;
+const RAM_SIGNATURE_ADDRESS: u16 = 0xA001;
+const RAM_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const RAM_TEXT_ADDRESS: u16 = 0xA004;
+const RAM_STATUS_RUNNING: u8 = 0x80;
+
+/// Run a blargg test ROM until it reports a result or `timeout_frames` passed. Fails as soon
+/// as execution diverges from the doctor's reference log.
+pub fn run(gb: &mut Gameboy, timeout_frames: u64) -> Report {
+    let mut serial = Vec::new();
+    for frame_idx in 1..=timeout_frames {
+        let frame = gb.run_frame();
+        if let Some(report) = diverged(&frame, frame_idx) {
+            return report;
+        }
+        serial.extend(frame.events.iter().filter_map(Event::serial_byte));
+
+        let output = String::from_utf8_lossy(&serial);
+        if let Some(outcome) = serial_outcome(&output) {
+            return Report {
+                outcome,
+                output: output.into_owned(),
+                frames: frame_idx,
+            };
+        }
+
+        if let Some((outcome, output)) = ram_result(gb) {
+            return Report {
+                outcome,
+                output,
+                frames: frame_idx,
+            };
+        }
+    }
+
+    Report {
+        outcome: Outcome::Timeout,
+        output: String::from_utf8_lossy(&serial).into_owned(),
+        frames: timeout_frames,
+    }
+}
+
+/// Look for the result in the serial output.
+fn serial_outcome(output: &str) -> Option<Outcome> {
+    if output.contains("Passed") {
+        Some(Outcome::Passed)
+    } else if output.contains("Failed") {
+        Some(Outcome::Failed)
+    } else {
+        None
+    }
+}
+
+/// Look for the result in cartridge RAM.
+fn ram_result(gb: &Gameboy) -> Option<(Outcome, String)> {
+    let mmu = &gb.cpu.bus;
+    let signature = [0, 1, 2].map(|i| mmu.read_u8(RAM_SIGNATURE_ADDRESS + i));
+    if signature != RAM_SIGNATURE {
+        return None;
+    }
+
+    let status = mmu.read_u8(RAM_STATUS_ADDRESS);
+    if status == RAM_STATUS_RUNNING {
+        return None;
+    }
+
+    let text: Vec<u8> = (RAM_TEXT_ADDRESS..0xC000)
+        .map(|address| mmu.read_u8(address))
+        .take_while(|byte| *byte != 0)
+        .collect();
+    let outcome = if status == 0 {
+        Outcome::Passed
+    } else {
+        Outcome::Failed
+    };
+    Some((outcome, String::from_utf8_lossy(&text).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{run, serial_outcome};
+    use crate::{
+        debug::Doctor,
+        frontend::NullFrontend,
+        gb::{Config, Gameboy},
+        harness::Outcome,
+    };
+
+    #[test]
+    fn detect_serial_outcome() {
+        assert_eq!(
+            serial_outcome("01-special\n\n\nPassed\n"),
+            Some(Outcome::Passed)
+        );
+        assert_eq!(
+            serial_outcome("cpu_instrs\n\nFailed 2 tests."),
+            Some(Outcome::Failed)
+        );
+        assert_eq!(serial_outcome("cpu_instrs\n\n01:ok "), None);
+    }
+
+    #[test]
+    fn fails_on_divergence() {
+        let mut gb = Gameboy::new(&vec![0; 0x8000], Box::new(NullFrontend), Config::default());
+        let reference =
+            "A:FF F:FF B:FF C:FF D:FF E:FF H:FF L:FF SP:FFFF PC:FFFF PCMEM:FF,FF,FF,FF\n";
+        gb.cpu.doctor = Some(Doctor::new(Box::new(Cursor::new(reference)), 4));
+        let report = run(&mut gb, 10);
+        assert_eq!(report.outcome, Outcome::Failed);
+        assert_eq!(report.frames, 1);
+        assert!(report.output.contains("should be"));
+    }
+}
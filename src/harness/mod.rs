@@ -0,0 +1,58 @@
+//! Harnesses for running test ROM suites headless and reporting whether they passed.
+
+pub mod blargg;
+pub mod mooneye;
+pub mod sm83;
+
+use std::fmt;
+
+use crate::gb::{self, Frame};
+
+/// How a test ROM finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    /// The ROM didn't report a result in time.
+    Timeout,
+}
+
+impl Outcome {
+    /// Process exit code for this outcome.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Passed => 0,
+            Self::Failed => 1,
+            Self::Timeout => 2,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passed => write!(f, "passed"),
+            Self::Failed => write!(f, "failed"),
+            Self::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+/// Result of running a test ROM.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub outcome: Outcome,
+    /// Text the ROM printed.
+    pub output: String,
+    /// Frames it took until the ROM reported its result.
+    pub frames: u64,
+}
+
+/// A failed report if execution diverged from the doctor's reference log during `frame`.
+fn diverged(frame: &Frame, frames: u64) -> Option<Report> {
+    gb::divergence(&frame.events).map(|divergence| Report {
+        outcome: Outcome::Failed,
+        output: divergence.to_string(),
+        frames,
+    })
+}
@@ -0,0 +1,78 @@
+//! Mooneye test suite.
+//!
+//! The ROMs report their result by executing `LD B,B`, which is treated as breakpoint. On
+//! success the registers B, C, D, E, H and L hold the fibonacci numbers 3, 5, 8, 13, 21 and 34,
+//! on failure they all hold `0x42`.
+
+use super::{diverged, Outcome, Report};
+use crate::{
+    cpu::registers::Registers,
+    gb::{Event, Gameboy},
+};
+
+/// Default number of frames after which a ROM counts as timed out.
+pub const DEFAULT_TIMEOUT_FRAMES: u64 = 60 * 20;
+
+/// Values of B, C, D, E, H and L when a test passed.
+const PASS_REGISTERS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+/// Run a mooneye test ROM until it hits the `LD B,B` breakpoint or `timeout_frames` passed.
+/// Fails as soon as execution diverges from the doctor's reference log.
+pub fn run(gb: &mut Gameboy, timeout_frames: u64) -> Report {
+    gb.cpu.breakpoints_enable = true;
+    for frame_idx in 1..=timeout_frames {
+        let frame = gb.run_frame();
+        if let Some(report) = diverged(&frame, frame_idx) {
+            return report;
+        }
+        if frame.events.contains(&Event::Breakpoint) {
+            let registers = gb.cpu.registers;
+            return Report {
+                outcome: outcome(&registers),
+                output: registers.to_string(),
+                frames: frame_idx,
+            };
+        }
+    }
+
+    Report {
+        outcome: Outcome::Timeout,
+        output: gb.cpu.registers.to_string(),
+        frames: timeout_frames,
+    }
+}
+
+/// Outcome reported by the registers at the breakpoint.
+fn outcome(registers: &Registers) -> Outcome {
+    let values = [
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+    ];
+    if values == PASS_REGISTERS {
+        Outcome::Passed
+    } else {
+        Outcome::Failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::outcome;
+    use crate::{cpu::registers::Registers, harness::Outcome};
+
+    #[test]
+    fn fibonacci_registers_pass() {
+        let mut registers = Registers::new();
+        registers.set_bc(0x0305);
+        registers.set_de(0x080D);
+        registers.set_hl(0x1522);
+        assert_eq!(outcome(&registers), Outcome::Passed);
+
+        registers.set_hl(0x4242);
+        assert_eq!(outcome(&registers), Outcome::Failed);
+    }
+}
@@ -0,0 +1,215 @@
+//! Runner for the SM83 single step test vectors
+//! (<https://github.com/SingleStepTests/sm83>).
+//!
+//! Each vector gives the cpu state and RAM before and after executing a single instruction and
+//! the bus cycles it takes. The instruction is executed on a [`FlatBus`] and its accesses are
+//! compared cycle by cycle. Parsing the JSON files is left to the caller.
+
+use std::fmt::Write;
+
+use crate::{
+    bus::{Access, Bus, FlatBus},
+    cpu::Cpu,
+    debug::Debug,
+};
+
+/// Cpu registers and RAM contents.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct State {
+    pub pc: u16,
+    pub sp: u16,
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub ime: bool,
+    /// Address, value pairs.
+    pub ram: Vec<(u16, u8)>,
+}
+
+/// A single test vector.
+#[derive(Debug, Clone, Default)]
+pub struct TestCase {
+    pub name: String,
+    pub initial: State,
+    pub expected: State,
+    /// Memory access of every machine cycle the instruction takes.
+    pub cycles: Vec<Access>,
+}
+
+impl State {
+    /// Load this state into `cpu`.
+    fn apply(&self, cpu: &mut Cpu<FlatBus>) {
+        cpu.registers.pc = self.pc;
+        cpu.registers.sp = self.sp;
+        cpu.registers.a = self.a;
+        cpu.registers.set_f(self.f);
+        cpu.registers.b = self.b;
+        cpu.registers.c = self.c;
+        cpu.registers.d = self.d;
+        cpu.registers.e = self.e;
+        cpu.registers.h = self.h;
+        cpu.registers.l = self.l;
+        cpu.set_interrupt_master_enable(self.ime);
+        for (address, val) in &self.ram {
+            cpu.bus.write_u8(*address, *val);
+        }
+    }
+
+    /// Read the state of `cpu`, reading RAM at the addresses of `like`.
+    fn capture(cpu: &Cpu<FlatBus>, like: &State) -> Self {
+        Self {
+            pc: cpu.registers.pc,
+            sp: cpu.registers.sp,
+            a: cpu.registers.a,
+            f: cpu.registers.f,
+            b: cpu.registers.b,
+            c: cpu.registers.c,
+            d: cpu.registers.d,
+            e: cpu.registers.e,
+            h: cpu.registers.h,
+            l: cpu.registers.l,
+            ime: cpu.interrupt_master_enable(),
+            ram: like
+                .ram
+                .iter()
+                .map(|(address, _)| (*address, cpu.bus.read_u8(*address)))
+                .collect(),
+        }
+    }
+}
+
+/// Run a single test vector.
+///
+/// # Errors
+/// Describes every difference to the expected state.
+pub fn run(test: &TestCase) -> Result<(), String> {
+    let mut cpu = Cpu::with_bus(FlatBus::new(), Debug::new(&[], false));
+    test.initial.apply(&mut cpu);
+    cpu.exec_instruction();
+    let cycles = cpu.bus.take_cycles();
+    let actual = State::capture(&cpu, &test.expected);
+
+    let mut errors = String::new();
+    let expected = &test.expected;
+    let registers = [
+        ("pc", actual.pc, expected.pc),
+        ("sp", actual.sp, expected.sp),
+        ("a", actual.a.into(), expected.a.into()),
+        ("f", actual.f.into(), expected.f.into()),
+        ("b", actual.b.into(), expected.b.into()),
+        ("c", actual.c.into(), expected.c.into()),
+        ("d", actual.d.into(), expected.d.into()),
+        ("e", actual.e.into(), expected.e.into()),
+        ("h", actual.h.into(), expected.h.into()),
+        ("l", actual.l.into(), expected.l.into()),
+        ("ime", actual.ime.into(), expected.ime.into()),
+    ];
+    for (name, actual, expected) in registers.into_iter().filter(|(_, a, e)| a != e) {
+        let _ = write!(errors, " {name}: 0x{actual:x} should be 0x{expected:x},");
+    }
+    for ((address, actual), (_, expected)) in actual.ram.iter().zip(&expected.ram) {
+        if actual != expected {
+            let _ = write!(
+                errors,
+                " (0x{address:0>4x}): 0x{actual:x} should be 0x{expected:x},"
+            );
+        }
+    }
+    if cycles.len() != test.cycles.len() {
+        let _ = write!(
+            errors,
+            " took {} cycles instead of {},",
+            cycles.len(),
+            test.cycles.len()
+        );
+    }
+    for (i, (actual, expected)) in cycles.iter().zip(&test.cycles).enumerate() {
+        if actual != expected {
+            let _ = write!(errors, " cycle {i}: {actual:?} should be {expected:?},");
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{}:{}", test.name, errors.trim_end_matches(',')))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, State, TestCase};
+    use crate::bus::Access;
+
+    #[test]
+    fn ld_bc_d16() {
+        let initial = State {
+            pc: 0x0100,
+            sp: 0xFFFE,
+            ram: vec![(0x0100, 0x01), (0x0101, 0x34), (0x0102, 0x12)],
+            ..State::default()
+        };
+        let mut test = TestCase {
+            name: "01 0000".into(),
+            expected: State {
+                pc: 0x0103,
+                b: 0x12,
+                c: 0x34,
+                ..initial.clone()
+            },
+            initial,
+            cycles: vec![
+                Access::Read(0x0100, 0x01),
+                Access::Read(0x0101, 0x34),
+                Access::Read(0x0102, 0x12),
+            ],
+        };
+        assert_eq!(run(&test), Ok(()));
+
+        test.expected.c = 0x35;
+        let err = run(&test).expect_err("c should differ");
+        assert_eq!(err, "01 0000: c: 0x34 should be 0x35");
+    }
+
+    #[test]
+    fn push_bc_cycles() {
+        let initial = State {
+            pc: 0x0100,
+            sp: 0xFFFE,
+            b: 0x12,
+            c: 0x34,
+            ram: vec![(0x0100, 0xC5), (0xFFFC, 0x00), (0xFFFD, 0x00)],
+            ..State::default()
+        };
+        let mut test = TestCase {
+            name: "c5 0000".into(),
+            expected: State {
+                pc: 0x0101,
+                sp: 0xFFFC,
+                ram: vec![(0x0100, 0xC5), (0xFFFC, 0x34), (0xFFFD, 0x12)],
+                ..initial.clone()
+            },
+            initial,
+            cycles: vec![
+                Access::Read(0x0100, 0xC5),
+                Access::Idle,
+                Access::Write(0xFFFD, 0x12),
+                Access::Write(0xFFFC, 0x34),
+            ],
+        };
+        assert_eq!(run(&test), Ok(()));
+
+        test.cycles.swap(2, 3);
+        let err = run(&test).expect_err("write order should differ");
+        assert_eq!(
+            err,
+            "c5 0000: cycle 2: Write(65533, 18) should be Write(65532, 52), \
+             cycle 3: Write(65532, 52) should be Write(65533, 18)"
+        );
+    }
+}
@@ -1,113 +1,295 @@
 use core::panic;
 
-use crate::cpu::interrupt::Interrupt;
+use crate::{cpu::interrupt::Interrupt, frontend::Button};
 
 // IO offset in memory
 const IO_OFFSET: usize = 0xFF00;
 // Size of IO range in memory
 const IO_SIZE: usize = 0x70;
 
-const SERIAL_BUFFER_SIZE: usize = 2;
-
 // IO Registers
+const REGISTER_P1_OFFSET: usize = 0x00;
+const REGISTER_SB_OFFSET: usize = 0x01;
+const REGISTER_SC_OFFSET: usize = 0x02;
 const REGISTER_DIV_OFFSET: usize = 0x04;
 const REGISTER_TIMA_OFFSET: usize = 0x05;
 const REGISTER_TMA_OFFSET: usize = 0x06;
 const REGISTER_TAC_OFFSET: usize = 0x07;
 
+/// System counter after the boot ROM, DIV reads `0xAB`.
+const SYSTEM_COUNTER_INIT_VAL: u16 = 0xABCC;
+
 pub struct Io {
     memory: [u8; IO_SIZE],
-    serial_buf: [u8; SERIAL_BUFFER_SIZE],
-    cycles: u8,
 
-    div: u8,
+    /// Button groups selected by writing P1, bit 4 directions, bit 5 buttons (active low).
+    p1_select: u8,
+    /// Pressed buttons, low nibble A, B, Select, Start, high nibble Right, Left, Up, Down.
+    pressed: u8,
+    /// A selected joypad line went low, the interrupt is requested on the next cycle.
+    joypad_interrupt: bool,
+
+    /// Serial transfer data.
+    sb: u8,
+    /// Serial transfer control.
+    sc: u8,
+    /// Bytes sent over the serial port, not yet taken.
+    serial_out: Vec<u8>,
+    /// A transfer finished, the interrupt is requested on the next cycle.
+    serial_interrupt: bool,
+
+    /// Internal counter incremented every dot, DIV is its upper byte.
+    system_counter: u16,
     tima: u8,
     tma: u8,
     tac: u8,
+    /// TIMA overflowed, it is reloaded from TMA and the interrupt requested on the next cycle.
+    tima_overflow: bool,
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Io {
     pub fn new() -> Self {
         Self {
-            cycles: 0,
             memory: [0; IO_SIZE],
-            serial_buf: [0; SERIAL_BUFFER_SIZE],
 
-            div: 0,
+            p1_select: 0x30,
+            pressed: 0,
+            joypad_interrupt: false,
+
+            sb: 0,
+            sc: 0,
+            serial_out: Vec::new(),
+            serial_interrupt: false,
+
+            system_counter: SYSTEM_COUNTER_INIT_VAL,
             tima: 0,
             tma: 0,
             tac: 0,
+            tima_overflow: false,
         }
     }
 
     pub fn read_u8(&self, address: u16) -> u8 {
         let address = address as usize - IO_OFFSET;
         match address {
-            REGISTER_DIV_OFFSET => self.div,
+            REGISTER_P1_OFFSET => 0b1100_0000 | self.p1_select | self.joypad_lines(),
+            REGISTER_SB_OFFSET => self.sb,
+            REGISTER_SC_OFFSET => self.sc | 0b0111_1110,
+            REGISTER_DIV_OFFSET => self.div(),
             REGISTER_TIMA_OFFSET => self.tima,
             REGISTER_TMA_OFFSET => self.tma,
-            REGISTER_TAC_OFFSET => self.tac,
+            REGISTER_TAC_OFFSET => self.tac | 0b1111_1000,
 
-            0x00..=IO_SIZE => self.memory[address],
+            0x03..IO_SIZE => self.memory[address],
             _ => panic!("invalid IO read"),
         }
     }
 
-    pub fn cycle(&mut self) -> Vec<Interrupt> {
+    /// Advance serial port and timer by one machine cycle.
+    pub fn tick(&mut self) -> Vec<Interrupt> {
         let mut interrupts = Vec::new();
-        if self.tima == 0xFF {
-            self.tima = self.tac;
-            interrupts.push(Interrupt::Timer);
+        if self.serial_interrupt {
+            self.serial_interrupt = false;
+            interrupts.push(Interrupt::Serial);
+        }
+        if self.joypad_interrupt {
+            self.joypad_interrupt = false;
+            interrupts.push(Interrupt::Joypad);
         }
 
-        self.div = self.div.wrapping_add(1);
-        if self.timer_enabled() && self.cycles % self.clock_select() == 0 {
-            self.tima = self.tima.wrapping_add(1);
+        if self.tima_overflow {
+            self.tima_overflow = false;
+            self.tima = self.tma;
+            interrupts.push(Interrupt::Timer);
         }
 
-        self.cycles = self.cycles.wrapping_add(1);
+        let counter = self.system_counter.wrapping_add(4);
+        self.set_system_counter(counter);
 
         interrupts
     }
 
+    /// Set the system counter, TIMA is incremented on a falling edge of the timer input.
+    fn set_system_counter(&mut self, val: u16) {
+        let before = self.timer_input();
+        self.system_counter = val;
+        self.tima_increment_on_falling_edge(before);
+    }
+
+    fn tima_increment_on_falling_edge(&mut self, before: bool) {
+        if !before || self.timer_input() {
+            return;
+        }
+
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.tima_overflow = overflow;
+    }
+
+    /// Press or release a button. A selected line going low requests the joypad interrupt.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let before = self.joypad_lines();
+        let mask = match button {
+            Button::A => 0x01,
+            Button::B => 0x02,
+            Button::Select => 0x04,
+            Button::Start => 0x08,
+            Button::Right => 0x10,
+            Button::Left => 0x20,
+            Button::Up => 0x40,
+            Button::Down => 0x80,
+        };
+        if pressed {
+            self.pressed |= mask;
+        } else {
+            self.pressed &= !mask;
+        }
+
+        if before & !self.joypad_lines() != 0 {
+            self.joypad_interrupt = true;
+        }
+    }
+
+    /// Lower nibble of P1, a selected, pressed button pulls its line low.
+    fn joypad_lines(&self) -> u8 {
+        let mut lines = 0x0F;
+        if self.p1_select & 0b0001_0000 == 0 {
+            lines &= !(self.pressed >> 4);
+        }
+        if self.p1_select & 0b0010_0000 == 0 {
+            lines &= !(self.pressed & 0x0F);
+        }
+        lines
+    }
+
+    /// The DIV register, upper byte of the system counter.
+    pub fn div(&self) -> u8 {
+        (self.system_counter >> 8) as u8
+    }
+
     pub fn reset_div(&mut self) {
-        self.div = 0x00;
+        self.set_system_counter(0);
+    }
+
+    /// The system counter bit selected by TAC, if the timer is enabled.
+    fn timer_input(&self) -> bool {
+        self.timer_enabled() && self.system_counter & self.clock_select() != 0
     }
 
     fn timer_enabled(&self) -> bool {
         self.tac & 0b100 == 0b100
     }
 
-    fn clock_select(&self) -> u8 {
-        let val = self.tac & 0b11;
-        match val {
-            0 => 255,
-            1 => 4,
-            2 => 16,
-            3 => 64,
-            _ => panic!("invalid clock select"),
+    /// Mask of the system counter bit driving TIMA.
+    fn clock_select(&self) -> u16 {
+        match self.tac & 0b11 {
+            0 => 1 << 9,
+            1 => 1 << 3,
+            2 => 1 << 5,
+            3 => 1 << 7,
+            _ => unreachable!("two bits"),
         }
     }
 
     pub fn write_u8(&mut self, address: u16, val: u8) {
         let address = address as usize - IO_OFFSET;
         match address {
+            REGISTER_P1_OFFSET => self.p1_select = val & 0b0011_0000,
+            REGISTER_SB_OFFSET => self.sb = val,
+            REGISTER_SC_OFFSET => self.serial_control(val),
             REGISTER_DIV_OFFSET => self.reset_div(),
-            REGISTER_TIMA_OFFSET => self.tima = val,
+            REGISTER_TIMA_OFFSET => {
+                // Writing TIMA in the cycle after an overflow cancels the reload.
+                self.tima = val;
+                self.tima_overflow = false;
+            }
             REGISTER_TMA_OFFSET => self.tma = val,
-            REGISTER_TAC_OFFSET => self.tac = val,
+            REGISTER_TAC_OFFSET => {
+                let before = self.timer_input();
+                self.tac = val;
+                self.tima_increment_on_falling_edge(before);
+            }
 
-            0x00..=IO_SIZE => self.memory[address] = val,
+            0x03..IO_SIZE => self.memory[address] = val,
             _ => panic!("invalid IO write"),
         }
     }
 
-    /// TODO: Add proper serial handling
-    fn serial_write(val: u8) {
-        let c = val as char;
-        if !c.is_ascii() || !c.is_whitespace() || !c.is_control() {
+    /// Write to SC. Starting a transfer with the internal clock sends SB right away,
+    /// there is never a peer on the other end, so `0xFF` is shifted in.
+    fn serial_control(&mut self, val: u8) {
+        self.sc = val;
+        if val & 0x81 != 0x81 {
             return;
         }
-        tracing::info!("[SERIAL]: {}", val as char);
+
+        tracing::debug!("[SERIAL]: {:?}", self.sb as char);
+        self.serial_out.push(self.sb);
+        self.sb = 0xFF;
+        self.sc &= 0x7F;
+        self.serial_interrupt = true;
+    }
+
+    /// Takes the bytes sent over the serial port since the last call.
+    pub fn take_serial(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.serial_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Io;
+    use crate::{cpu::interrupt::Interrupt, frontend::Button};
+
+    #[test]
+    fn joypad() {
+        let mut io = Io::new();
+        io.write_u8(0xFF00, 0x20);
+        io.set_button(Button::A, true);
+        // Directions selected, A isn't visible and doesn't interrupt.
+        assert_eq!(io.read_u8(0xFF00), 0xEF);
+        assert!(io.tick().is_empty());
+
+        io.set_button(Button::Down, true);
+        assert_eq!(io.read_u8(0xFF00), 0xE7);
+        assert!(matches!(io.tick()[..], [Interrupt::Joypad]));
+
+        io.write_u8(0xFF00, 0x10);
+        assert_eq!(io.read_u8(0xFF00), 0xDE);
+    }
+
+    #[test]
+    fn div_increments_every_64_cycles() {
+        let mut io = Io::new();
+        io.write_u8(0xFF04, 0);
+        for _ in 0..63 {
+            io.tick();
+        }
+        assert_eq!(io.read_u8(0xFF04), 0);
+        io.tick();
+        assert_eq!(io.read_u8(0xFF04), 1);
+    }
+
+    #[test]
+    fn tima_overflow_reloads_one_cycle_later() {
+        let mut io = Io::new();
+        io.write_u8(0xFF04, 0);
+        io.write_u8(0xFF06, 0x42);
+        io.write_u8(0xFF05, 0xFF);
+        // Enabled, increment every 4 machine cycles.
+        io.write_u8(0xFF07, 0b101);
+        for _ in 0..4 {
+            assert!(io.tick().is_empty());
+        }
+        assert_eq!(io.read_u8(0xFF05), 0x00);
+        let interrupts = io.tick();
+        assert!(matches!(interrupts[..], [Interrupt::Timer]));
+        assert_eq!(io.read_u8(0xFF05), 0x42);
     }
 }
@@ -0,0 +1,32 @@
+//! Rustboy, a Gameboy emulator.
+//!
+//! The core (cpu, memory, ppu, cartridges) is frontend-agnostic. Frontends implement
+//! [`Frontend`] and get handed finished frames, so the core can be embedded without
+//! linking SDL.
+
+#![forbid(unsafe_code)]
+#![deny(nonstandard_style)]
+#![warn(clippy::pedantic, clippy::unwrap_used)]
+#![allow(clippy::upper_case_acronyms, clippy::similar_names, clippy::module_name_repetitions, clippy::cast_possible_truncation, clippy::cast_lossless, clippy::must_use_candidate, clippy::missing_panics_doc, /* remove */ dead_code)]
+
+pub mod apu;
+pub mod bus;
+pub mod cpu;
+pub mod debug;
+pub mod dma;
+pub mod frontend;
+pub mod gb;
+pub mod harness;
+pub mod io;
+pub mod mbc;
+pub mod mmu;
+pub mod pacing;
+pub mod palette;
+pub mod ppu;
+
+pub use bus::Bus;
+pub use cpu::Cpu;
+pub use frontend::Frontend;
+pub use gb::Gameboy;
+pub use mbc::load_cartridge;
+pub use mmu::Mmu;
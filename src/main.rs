@@ -3,22 +3,22 @@
 #![warn(clippy::pedantic, clippy::unwrap_used)]
 #![allow(clippy::upper_case_acronyms, clippy::similar_names, clippy::module_name_repetitions, clippy::cast_possible_truncation, clippy::cast_lossless, /* remove */ dead_code)]
 
-use std::{fs, path};
+use std::{
+    fs,
+    path::{self, PathBuf},
+    process,
+};
 
 use clap::Parser;
+use rustboy::{
+    cpu::disassembler::disassemble_rom,
+    debug::{self, Doctor},
+    gb,
+    palette::{self, ColorScheme},
+    Gameboy,
+};
 use tracing_subscriber::EnvFilter;
 
-use crate::cpu::disassembler::disassemble_rom;
-use crate::gb::Gameboy;
-
-mod apu;
-pub mod cpu;
-mod debug;
-mod gb;
-mod io;
-mod mbc;
-mod mmu;
-mod ppu;
 mod sdl;
 
 /// Command line arguments, parsed by [`clap`].
@@ -32,10 +32,39 @@ pub struct Args {
     uncap_clock_speed: bool,
     #[arg(long, action)]
     enable_gbd: bool,
+    /// Compare execution against this gameboy doctor reference log (plain or gzipped).
+    #[arg(long)]
+    gbd_reference: Option<PathBuf>,
     #[arg(long, action)]
     enable_trace: bool,
     #[arg(long, action)]
     serial_to_stdout: bool,
+    /// Draw through the cycle-accurate pixel FIFO, for mid-scanline effects.
+    #[arg(long, action)]
+    pixel_fifo: bool,
+    /// Pace emulation by display vsync instead of the DMG's 59.7275 Hz, for smooth scrolling
+    /// on 60 Hz displays.
+    #[arg(long, action)]
+    vsync: bool,
+    /// Colour scheme to start with, a preset or one from `--palette-config`.
+    #[arg(long)]
+    palette: Option<String>,
+    /// TOML file with custom colour schemes.
+    #[arg(long)]
+    palette_config: Option<PathBuf>,
+}
+
+impl From<Args> for gb::Config {
+    fn from(args: Args) -> Self {
+        Self {
+            gb_doctor_enable: args.enable_gbd,
+            uncap_clock_speed: args.uncap_clock_speed,
+            serial_to_stdout: args.serial_to_stdout,
+            pixel_fifo: args.pixel_fifo,
+            vsync: args.vsync,
+            ..Self::default()
+        }
+    }
 }
 
 fn main() {
@@ -56,18 +85,49 @@ fn main() {
         return;
     }
 
-    let sdl_ctx = sdl2::init().unwrap();
-    let mut event_pump = sdl_ctx.event_pump().unwrap();
-    let renderer = sdl::Renderer::new(sdl::Config::default(), &sdl_ctx).unwrap();
-
-    let mut gb = Gameboy::new(&rom, renderer, args.into());
-    // TODO: remove callback in favor of threading
-    gb.run(|| {
-        for event in event_pump.poll_iter() {
-            match event {
-                sdl2::event::Event::Quit { .. } => std::process::exit(0),
-                _ => {}
+    let mut color_schemes = ColorScheme::presets();
+    if let Some(path) = &args.palette_config {
+        match ColorScheme::load(path) {
+            Ok(schemes) => color_schemes.extend(schemes),
+            Err(err) => {
+                eprintln!("{err}");
+                process::exit(1);
             }
         }
+    }
+    let color_scheme = match &args.palette {
+        Some(name) => palette::find(&color_schemes, name).unwrap_or_else(|| {
+            eprintln!("unknown colour scheme {name:?}");
+            process::exit(1);
+        }),
+        None => 0,
+    };
+
+    let sdl_ctx = sdl2::init().expect("cannot initialize sdl");
+    let sdl_cfg = sdl::Config {
+        color_schemes,
+        color_scheme,
+        vsync: args.vsync,
+        ..sdl::Config::default()
+    };
+    let renderer = sdl::Renderer::new(sdl_cfg, &sdl_ctx).expect("cannot create renderer");
+    let audio = sdl::Audio::new(&sdl_ctx);
+
+    let doctor = args.gbd_reference.as_deref().map(|path| {
+        Doctor::open(path, debug::DEFAULT_HISTORY_LEN).expect("cannot open reference log")
     });
+
+    let mut gb = Gameboy::new(&rom, Box::new(renderer), args.into());
+    match audio {
+        Ok(audio) => gb.set_audio_sink(Box::new(audio)),
+        Err(err) => tracing::warn!(?err, "cannot open audio device, running muted"),
+    }
+    if doctor.is_some() {
+        gb.cpu.bus.set_ly_stub(true);
+    }
+    gb.cpu.doctor = doctor;
+    if let Some(divergence) = gb.run() {
+        eprint!("{divergence}");
+        process::exit(1);
+    }
 }
@@ -16,17 +16,15 @@ const RAM_SIZE: usize = 0x2000;
 /// MBC0 doesn't exist and mimics the behaviour when no MBC
 /// is present on the rom.
 pub(super) struct MBC0 {
-    rom: [u8; ROM_MEMORY_SIZE],
+    rom: Vec<u8>,
     ram: [u8; RAM_SIZE],
 }
 
 impl MBC0 {
     pub fn new(rom: &[u8]) -> Self {
         assert!(rom.len() <= ROM_MEMORY_SIZE);
-        let mut rom_mem = [0x00; ROM_MEMORY_SIZE];
-        for (addr, byte) in rom.iter().enumerate() {
-            rom_mem[addr] = *byte;
-        }
+        let mut rom_mem = vec![0x00; ROM_MEMORY_SIZE];
+        rom_mem[..rom.len()].copy_from_slice(rom);
         Self {
             rom: rom_mem,
             ram: [0x00; RAM_SIZE],
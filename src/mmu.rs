@@ -21,14 +21,11 @@ use std::array;
 
 use crate::{
     apu::Apu,
-    cpu::{
-        interrupt::Interrupt,
-        utils::{self, split_u16},
-    },
+    bus::Bus,
+    dma::Dma,
     io::Io,
     mbc::{self, MBC},
     ppu::Ppu,
-    sdl::Renderer,
 };
 
 /// Gameboy wram size.
@@ -39,6 +36,9 @@ const WRAM_ECHO_OFFSET: u16 = 0x2000;
 
 const HRAM_SIZE: usize = 0x7F;
 
+/// Dots (T-cycles) per machine cycle.
+const DOTS_PER_M_CYCLE: usize = 4;
+
 /// Memory management unit. Handle and map memory access.
 pub struct Mmu {
     wram: [u8; WRAM_SIZE],
@@ -46,24 +46,32 @@ pub struct Mmu {
     ppu: Ppu,
     apu: Apu,
     io: Io,
+    dma: Dma,
     mbc: Box<dyn MBC>,
 
     pub interrupt_enable: u8,
+    /// Requested interrupts (IF), the cpu handles them through the bus.
+    pub interrupt_flag: u8,
+    /// LY always reads 0x90, as gameboy doctor logs expect.
+    ly_stub: bool,
     debug: crate::debug::Debug,
 }
 
 impl Mmu {
     /// Create new wram.
-    pub fn new(rom: &[u8], debug: crate::debug::Debug, renderer: Renderer) -> Self {
+    pub fn new(rom: &[u8], debug: crate::debug::Debug) -> Self {
         tracing::info!("initializing mmu");
         let mut mmu = Self {
             wram: array::from_fn(|_| rand::random()),
             hram: array::from_fn(|_| rand::random()),
-            ppu: Ppu::new(renderer),
+            ppu: Ppu::new(),
             apu: Apu::new(),
             mbc: mbc::load_cartridge(rom),
             io: Io::new(),
+            dma: Dma::new(),
             interrupt_enable: 0,
+            interrupt_flag: 0,
+            ly_stub: debug.gb_doc_enable,
             debug,
         };
         mmu.initial_write();
@@ -77,7 +85,8 @@ impl Mmu {
         self.write_u8(0xFF10, 0x80);
         self.write_u8(0xFF11, 0xBF);
         self.write_u8(0xFF12, 0xF3);
-        self.write_u8(0xFF14, 0xBF);
+        // Without the trigger bit, the boot sound already played.
+        self.write_u8(0xFF14, 0x3F);
         self.write_u8(0xFF16, 0x3F);
         self.write_u8(0xFF16, 0x3F);
         self.write_u8(0xFF17, 0x00);
@@ -93,6 +102,7 @@ impl Mmu {
         self.write_u8(0xFF24, 0x77);
         self.write_u8(0xFF25, 0xF3);
         self.write_u8(0xFF26, 0xF1);
+        self.apu.post_boot();
         self.write_u8(0xFF40, 0x91);
         self.write_u8(0xFF42, 0x00);
         self.write_u8(0xFF43, 0x00);
@@ -104,35 +114,57 @@ impl Mmu {
         self.write_u8(0xFF4B, 0x00);
     }
 
-    // cycle does one execution cycle of the mmu and all associated
-    // parts, like the ppu. It returns all requested interrupts during
-    // the cycle.
-    pub fn cycle(&mut self) -> Vec<Interrupt> {
-        let mut interrupts = Vec::new();
-        interrupts.append(&mut self.ppu.cycle());
-        interrupts.append(&mut self.io.cycle());
-        interrupts
+    /// The picture processing unit.
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    /// The picture processing unit, mutably.
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    /// The audio processing unit, mutably.
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
     }
 
-    /// Reads from wram at address.
-    pub fn read_u8(&self, address: u16) -> u8 {
+    /// The IO registers, mutably.
+    pub fn io_mut(&mut self) -> &mut Io {
+        &mut self.io
+    }
+
+    /// Stub LY to 0x90 for comparing against gameboy doctor logs.
+    pub fn set_ly_stub(&mut self, enable: bool) {
+        self.ly_stub = enable;
+    }
+
+    /// Reads from memory at address, ignoring OAM DMA.
+    fn read(&self, address: u16) -> u8 {
         match address {
             // ROM, BANKS
             0x0000..=0x7FFF => self.mbc.read_rom(address),
-            // VRAM
-            0x8000..=0x9FFF => self.ppu.read_u8(address),
+            // VRAM, OAM, locked depending on the ppu mode
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F if !self.ppu.cpu_accessible(address) => 0xFF,
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.read_u8(address),
             // External RAM
             0xA000..=0xBFFF => self.mbc.read_ram(address),
             // WRAM
             0xC000..=0xDFFF => self.wram[address as usize - 0xC000],
             // Echo RAM
-            0xE000..=0xFDFF => self.read_u8(address - WRAM_ECHO_OFFSET),
-            // OAM
-            0xFE00..=0xFE9F => todo!("OAM read"),
+            0xE000..=0xFDFF => self.read(address - WRAM_ECHO_OFFSET),
             // Not Usable
             0xFEA0..=0xFEFF => 0xFF,
             // PPU LY REGISTER
-            0xFF44 => 0x90,
+            0xFF44 if self.ly_stub => 0x90,
+            // OAM DMA
+            0xFF46 => self.dma.read_register(),
+            // LCD registers
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.read_register(address),
+            // Sound registers
+            0xFF10..=0xFF3F => self.apu.read_register(address),
+            // Interrupt Flag, upper bits unused
+            0xFF0F => 0b1110_0000 | self.interrupt_flag,
             // IO
             0xFF00..=0xFF7F => self.io.read_u8(address),
             // HRAM
@@ -142,31 +174,40 @@ impl Mmu {
         }
     }
 
-    pub fn read_u16(&self, address: u16) -> u16 {
-        let l = self.read_u8(address);
-        let h = self.read_u8(address + 1);
-        utils::merge_u8s(h, l)
+    /// Reads from memory at address for OAM DMA, which the ppu doesn't lock out of VRAM and OAM.
+    fn dma_read(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.read_u8(address),
+            _ => self.read(address),
+        }
     }
 
-    /// Writes u8 to wram at address.
-    pub fn write_u8(&mut self, address: u16, val: u8) {
+    /// Writes u8 to memory at address, ignoring OAM DMA.
+    fn write(&mut self, address: u16, val: u8) {
         match address {
             // ROM, BANKS
             0x0000..=0x7FFF => self.mbc.write_rom(address, val),
-            // VRAM
-            0x8000..=0x9FFF => self.ppu.write_u8(address, val),
+            // VRAM, OAM, locked depending on the ppu mode
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F if !self.ppu.cpu_accessible(address) => (),
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.write_u8(address, val),
             // External RAM
             0xA000..=0xBFFF => self.mbc.write_ram(address, val),
             // WRAM
             0xC000..=0xDFFF => self.wram[address as usize - 0xC000] = val,
             // Echo RAM
-            0xE000..=0xFDFF => self.write_u8(address - WRAM_ECHO_OFFSET, val),
-            // OAM
-            0xFE00..=0xFE9F => todo!("OAM write"),
+            0xE000..=0xFDFF => self.write(address - WRAM_ECHO_OFFSET, val),
             // Not Usable
             0xFEA0..=0xFEFF => (),
-            // PPU LY REGISTER
-            0xFF44 => (),
+            // OAM DMA
+            0xFF46 => self.dma.write_register(val),
+            // LCD registers
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => {
+                self.ppu.write_register(address, val);
+            }
+            // Sound registers
+            0xFF10..=0xFF3F => self.apu.write_register(address, val),
+            // Interrupt Flag
+            0xFF0F => self.interrupt_flag = val & 0x1F,
             // IO
             0xFF00..=0xFF7F => self.io.write_u8(address, val),
             // HRAM
@@ -175,11 +216,131 @@ impl Mmu {
             0xFFFF => self.interrupt_enable = val,
         }
     }
+}
+
+impl Bus for Mmu {
+    // tick advances the mmu and all associated parts, like the ppu,
+    // by one machine cycle (four dots). Interrupts requested during
+    // the cycle are set in IF.
+    fn tick(&mut self) {
+        let mut interrupts = Vec::new();
+        for _ in 0..DOTS_PER_M_CYCLE {
+            interrupts.append(&mut self.ppu.cycle());
+        }
+        interrupts.append(&mut self.io.tick());
+        self.apu.tick(self.io.div());
+        if let Some((source, index)) = self.dma.tick() {
+            let byte = self.dma_read(source);
+            self.dma.transferred(byte);
+            self.ppu.write_oam(index, byte);
+        }
+        for interrupt in interrupts {
+            self.interrupt_flag |= 1 << interrupt.bit_index();
+        }
+    }
+
+    /// Reads from memory at address. During OAM DMA the cpu only sees HRAM and the IO
+    /// registers unaffected.
+    fn read_u8(&self, address: u16) -> u8 {
+        self.dma
+            .conflict(address)
+            .unwrap_or_else(|| self.read(address))
+    }
 
-    /// Writes u16 to wram at address.
-    pub fn write_u16(&mut self, address: u16, val: u16) {
-        let (h, l) = split_u16(val);
-        self.write_u8(address, l);
-        self.write_u8(address + 1, h);
+    /// Writes u8 to memory at address, dropped if it conflicts with OAM DMA.
+    fn write_u8(&mut self, address: u16, val: u8) {
+        if self.dma.conflict(address).is_none() {
+            self.write(address, val);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mmu;
+    use crate::{bus::Bus, debug::Debug};
+
+    #[test]
+    fn silent_after_boot() {
+        let mut mmu = Mmu::new(&vec![0; 0x8000], Debug::new(&[], false));
+        assert_eq!(mmu.read_u8(0xFF26), 0xF1);
+        for _ in 0..0x1000 {
+            mmu.tick();
+            assert_eq!(mmu.apu.channel_output(), [0; 4]);
+        }
+    }
+
+    #[test]
+    fn interrupt_flag_upper_bits_read_set() {
+        let mut mmu = Mmu::new(&vec![0; 0x8000], Debug::new(&[], false));
+        mmu.write_u8(0xFF0F, 0xFF);
+        assert_eq!(mmu.interrupt_flag, 0x1F);
+        mmu.write_u8(0xFF0F, 0x04);
+        assert_eq!(mmu.read_u8(0xFF0F), 0xE4);
+    }
+
+    #[test]
+    fn oam_dma() {
+        let mut mmu = Mmu::new(&vec![0; 0x8000], Debug::new(&[], false));
+        // Keep the ppu from locking OAM.
+        mmu.write_u8(0xFF40, 0x00);
+        for i in 0..0xA0 {
+            mmu.write_u8(0xC100 + i, i as u8);
+        }
+        mmu.write_u8(0xFF80, 0x12);
+        mmu.write_u8(0xFF46, 0xC1);
+        mmu.tick();
+        for _ in 0..0x9F {
+            mmu.tick();
+            assert_eq!(mmu.read_u8(0xFE00), 0xFF);
+            assert_eq!(mmu.read_u8(0xFF80), 0x12);
+        }
+        // Bus conflict, the cpu sees the byte being transferred.
+        assert_eq!(mmu.read_u8(0xC000), 0x9E);
+        mmu.tick();
+        assert_eq!(mmu.read_u8(0xFE00), 0x00);
+        assert_eq!(mmu.read_u8(0xFE9F), 0x9F);
+    }
+
+    #[test]
+    fn vram_and_oam_locked_by_ppu_mode() {
+        let mut mmu = Mmu::new(&vec![0; 0x8000], Debug::new(&[], false));
+        mmu.write_u8(0xFF40, 0x00);
+        mmu.write_u8(0x8000, 0x12);
+        for i in 1..0xA0 {
+            mmu.write_u8(0x8000 + i, i as u8);
+        }
+        mmu.write_u8(0xFE00, 0x34);
+        mmu.write_u8(0xFF40, 0x80);
+
+        // Mode 2, OAM scan.
+        assert_eq!(mmu.read_u8(0x8000), 0x12);
+        assert_eq!(mmu.read_u8(0xFE00), 0xFF);
+        mmu.write_u8(0xFE00, 0x00);
+        // Mode 3, pixel transfer.
+        for _ in 0..20 {
+            mmu.tick();
+        }
+        assert_eq!(mmu.read_u8(0xFF41) & 0b11, 3);
+        assert_eq!(mmu.read_u8(0x8000), 0xFF);
+        assert_eq!(mmu.read_u8(0xFE00), 0xFF);
+        mmu.write_u8(0x8000, 0x00);
+        // Mode 0, H-Blank.
+        for _ in 0..43 {
+            mmu.tick();
+        }
+        assert_eq!(mmu.read_u8(0xFF41) & 0b11, 0);
+        assert_eq!(mmu.read_u8(0x8000), 0x12);
+        assert_eq!(mmu.read_u8(0xFE00), 0x34);
+
+        // OAM DMA copies from VRAM through the next line's pixel transfer.
+        mmu.write_u8(0xFF46, 0x80);
+        for _ in 0..=0xA0 {
+            mmu.tick();
+        }
+        assert_eq!(mmu.ppu().read_u8(0xFE00), 0x12);
+        for i in 1..0xA0 {
+            assert_eq!(mmu.ppu().read_u8(0xFE00 + i), i as u8);
+        }
     }
 }
@@ -0,0 +1,145 @@
+//! Frame pacing. [`Gameboy::run`](crate::Gameboy::run) emulates a frame at a time and then
+//! waits for the next one to be due, at the DMG's 59.7275 Hz or on display vsync. The audio
+//! buffer is kept at its target fill by nudging the APU's resampling ratio, so the two clocks
+//! can't drift apart.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::apu::SAMPLE_RATE;
+
+/// Frames per second, a frame takes 70224 of the 4194304 dots per second.
+pub const FRAME_RATE: f64 = 4_194_304.0 / 70_224.0;
+
+/// Frames the pacer may fall behind before it stops catching up.
+const MAX_LAG_FRAMES: u32 = 4;
+
+/// Audio queued ahead, in seconds. Enough to ride out a late frame.
+const AUDIO_LATENCY: f64 = 0.05;
+
+/// Most audio queued, in multiples of the target fill. Samples beyond it are dropped so the
+/// latency stays bounded when playback stalls.
+const MAX_QUEUED_TARGETS: usize = 4;
+
+/// Largest change of the resampling ratio, 0.5% is well below audible pitch changes.
+const MAX_RATE_DELTA: f64 = 0.005;
+
+/// How the emulator waits between frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pacing {
+    /// Sleep until the next frame is due.
+    #[default]
+    Timer,
+    /// Presenting a frame blocks until display vsync, don't wait any longer. Frames that
+    /// aren't presented, while the LCD is off, are paced by the timer.
+    Vsync,
+    /// Run as fast as possible.
+    Uncapped,
+}
+
+#[derive(Debug)]
+pub struct Pacer {
+    pacing: Pacing,
+    frame: Duration,
+    /// When the next frame is due.
+    next: Instant,
+}
+
+impl Pacer {
+    pub fn new(pacing: Pacing) -> Self {
+        Self {
+            pacing,
+            frame: Duration::from_secs_f64(1.0 / FRAME_RATE),
+            next: Instant::now(),
+        }
+    }
+
+    /// Wait until the next frame is due, `presented` tells whether the frame was shown. After
+    /// falling several frames behind, e.g. because the process was suspended, the schedule
+    /// restarts instead of rushing to catch up.
+    pub fn wait(&mut self, presented: bool) {
+        match self.pacing {
+            Pacing::Uncapped => return,
+            Pacing::Vsync if presented => {
+                // Already waited for vsync, restart the schedule from there.
+                self.next = Instant::now();
+                return;
+            }
+            Pacing::Timer | Pacing::Vsync => {}
+        }
+        self.next += self.frame;
+        let now = Instant::now();
+        if let Some(ahead) = self.next.checked_duration_since(now) {
+            thread::sleep(ahead);
+        } else if now - self.next > self.frame * MAX_LAG_FRAMES {
+            tracing::debug!(behind = ?(now - self.next), "can't keep up, skipping ahead");
+            self.next = now;
+        }
+    }
+}
+
+/// Samples, interleaved stereo, the audio buffer is kept at.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn target_queued() -> usize {
+    (f64::from(SAMPLE_RATE) * AUDIO_LATENCY) as usize * 2
+}
+
+/// Output sample rate for the APU, given the samples still queued for playback. Below the
+/// target fill the APU produces slightly more samples, above it slightly fewer.
+#[allow(clippy::cast_precision_loss)]
+pub fn sample_rate(queued: usize) -> f64 {
+    let fill = queued as f64 / target_queued() as f64;
+    let ratio = 1.0 + MAX_RATE_DELTA * (1.0 - fill).clamp(-1.0, 1.0);
+    f64::from(SAMPLE_RATE) * ratio
+}
+
+/// Silence to queue so playback doesn't run dry, on start or after a stall. The rate control
+/// is far too gentle to refill an empty buffer.
+pub fn underrun_padding(queued: usize) -> usize {
+    if queued < target_queued() / 4 {
+        // Whole stereo frames.
+        (target_queued() - queued) & !1
+    } else {
+        0
+    }
+}
+
+/// Samples that can still be queued on top of `queued` before the buffer overflows.
+pub fn queue_room(queued: usize) -> usize {
+    // Whole stereo frames.
+    (target_queued() * MAX_QUEUED_TARGETS).saturating_sub(queued) & !1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{queue_room, sample_rate, target_queued, underrun_padding};
+
+    #[test]
+    fn rate_control() {
+        let target = target_queued();
+        assert!((sample_rate(target) - 48_000.0).abs() < f64::EPSILON);
+        assert!((sample_rate(0) - 48_240.0).abs() < 1e-9);
+        assert!((sample_rate(target * 2) - 47_760.0).abs() < 1e-9);
+        assert!((sample_rate(target * 10) - 47_760.0).abs() < 1e-9);
+        assert!(sample_rate(target / 2) > 48_000.0);
+    }
+
+    #[test]
+    fn pads_underruns() {
+        let target = target_queued();
+        assert_eq!(underrun_padding(0), target);
+        assert_eq!(underrun_padding(100), target - 100);
+        assert_eq!(underrun_padding(target / 2), 0);
+    }
+
+    #[test]
+    fn drops_overflow() {
+        let target = target_queued();
+        assert_eq!(queue_room(0), target * 4);
+        assert_eq!(queue_room(target * 4 - 3), 2);
+        assert_eq!(queue_room(target * 4), 0);
+        assert_eq!(queue_room(target * 10), 0);
+    }
+}
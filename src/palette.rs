@@ -0,0 +1,292 @@
+//! Colour schemes, mapping the four shades of the DMG to RGB colours. Background, OBJ0 and OBJ1
+//! can be coloured separately, the way the CGB boot ROM colourises DMG games.
+//!
+//! Custom schemes are loaded from a TOML file:
+//!
+//! ```toml
+//! [[scheme]]
+//! name = "Virtual Boy"
+//! bg = ["#EF0000", "#A40000", "#550000", "#000000"]
+//! # Optional, fall back to `bg`.
+//! obj0 = ["#FFFFFF", "#EF0000", "#A40000", "#000000"]
+//! obj1 = ["#FFFFFF", "#EF0000", "#A40000", "#000000"]
+//! ```
+
+use std::{fmt, fs, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    frontend::GREYSCALE,
+    ppu::{PaletteRegister, Pixel},
+};
+
+/// Red, green and blue.
+pub type Rgb = [u8; 3];
+/// Colours of the four shades, lightest first.
+pub type Shades = [Rgb; 4];
+
+const GREY: Shades = [
+    [GREYSCALE[0]; 3],
+    [GREYSCALE[1]; 3],
+    [GREYSCALE[2]; 3],
+    [GREYSCALE[3]; 3],
+];
+const DMG_GREEN: Shades = [
+    [0x9B, 0xBC, 0x0F],
+    [0x8B, 0xAC, 0x0F],
+    [0x30, 0x62, 0x30],
+    [0x0F, 0x38, 0x0F],
+];
+const POCKET: Shades = [
+    [0xC4, 0xCF, 0xA1],
+    [0x8B, 0x95, 0x6D],
+    [0x4D, 0x53, 0x3C],
+    [0x1F, 0x1F, 0x1F],
+];
+const LIGHT: Shades = [
+    [0x00, 0xC8, 0xA0],
+    [0x00, 0x9C, 0x7C],
+    [0x00, 0x6A, 0x54],
+    [0x00, 0x38, 0x2C],
+];
+const HIGH_CONTRAST: Shades = [
+    [0xFF, 0xFF, 0xFF],
+    [0xFF, 0xD8, 0x00],
+    [0x00, 0x50, 0xC8],
+    [0x00, 0x00, 0x00],
+];
+/// Yellow, orange and blue stay apart with all common colour vision deficiencies.
+const COLOR_BLIND: Shades = [
+    [0xF0, 0xE4, 0x42],
+    [0xE6, 0x9F, 0x00],
+    [0x00, 0x72, 0xB2],
+    [0x00, 0x00, 0x00],
+];
+/// Palette the CGB boot ROM picks for DMG games it doesn't know.
+const CGB_BG: Shades = [
+    [0xFF, 0xFF, 0xFF],
+    [0x7B, 0xFF, 0x31],
+    [0x00, 0x63, 0xC5],
+    [0x00, 0x00, 0x00],
+];
+const CGB_OBJ: Shades = [
+    [0xFF, 0xFF, 0xFF],
+    [0xFF, 0x84, 0x84],
+    [0x94, 0x3A, 0x3A],
+    [0x00, 0x00, 0x00],
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorScheme {
+    pub name: String,
+    pub bg: Shades,
+    pub obj0: Shades,
+    pub obj1: Shades,
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        Self::uniform("Grey", GREY)
+    }
+}
+
+impl ColorScheme {
+    /// Scheme using the same colours for every layer.
+    pub fn uniform(name: &str, shades: Shades) -> Self {
+        Self {
+            name: name.into(),
+            bg: shades,
+            obj0: shades,
+            obj1: shades,
+        }
+    }
+
+    /// Colour of `pixel`.
+    pub fn color(&self, pixel: Pixel) -> Rgb {
+        let shades = match pixel.palette {
+            PaletteRegister::Bgp => &self.bg,
+            PaletteRegister::Obp0 => &self.obj0,
+            PaletteRegister::Obp1 => &self.obj1,
+        };
+        shades[usize::from(pixel.shade & 0b11)]
+    }
+
+    /// Built in schemes, the default first.
+    pub fn presets() -> Vec<Self> {
+        vec![
+            Self::default(),
+            Self::uniform("DMG", DMG_GREEN),
+            Self::uniform("Pocket", POCKET),
+            Self::uniform("Light", LIGHT),
+            Self::uniform("High contrast", HIGH_CONTRAST),
+            Self::uniform("Colour blind", COLOR_BLIND),
+            Self {
+                name: "CGB".into(),
+                bg: CGB_BG,
+                obj0: CGB_OBJ,
+                obj1: CGB_OBJ,
+            },
+        ]
+    }
+
+    /// Load custom schemes from the TOML file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be read or isn't a valid scheme config.
+    pub fn load(path: &Path) -> Result<Vec<Self>, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse custom schemes from a TOML config.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `config` isn't valid TOML, misses fields or has malformed colours.
+    pub fn parse(config: &str) -> Result<Vec<Self>, Error> {
+        let config: Config = toml::from_str(config)?;
+        config
+            .scheme
+            .into_iter()
+            .map(|scheme| {
+                let bg = parse_shades(&scheme.bg)?;
+                let obj0 = scheme.obj0.as_ref().map_or(Ok(bg), parse_shades)?;
+                let obj1 = scheme.obj1.as_ref().map_or(Ok(bg), parse_shades)?;
+                Ok(Self {
+                    name: scheme.name,
+                    bg,
+                    obj0,
+                    obj1,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Index of the scheme called `name`, ignoring case.
+pub fn find(schemes: &[ColorScheme], name: &str) -> Option<usize> {
+    schemes
+        .iter()
+        .position(|scheme| scheme.name.eq_ignore_ascii_case(name))
+}
+
+/// Scheme config file.
+#[derive(Debug, Deserialize)]
+struct Config {
+    #[serde(default)]
+    scheme: Vec<SchemeConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SchemeConfig {
+    name: String,
+    bg: [String; 4],
+    obj0: Option<[String; 4]>,
+    obj1: Option<[String; 4]>,
+}
+
+/// Parse four `#RRGGBB` colours.
+fn parse_shades(colors: &[String; 4]) -> Result<Shades, Error> {
+    let mut shades = Shades::default();
+    for (shade, color) in shades.iter_mut().zip(colors) {
+        *shade = parse_color(color)?;
+    }
+    Ok(shades)
+}
+
+/// Parse a `#RRGGBB` colour, the `#` is optional.
+fn parse_color(color: &str) -> Result<Rgb, Error> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| Error::Color(color.into()))?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Ok([r, g, b])
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    /// A colour that isn't `#RRGGBB`.
+    Color(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "cannot read colour scheme config: {err}"),
+            Self::Toml(err) => write!(f, "invalid colour scheme config: {err}"),
+            Self::Color(color) => write!(f, "invalid colour {color:?}, expected #RRGGBB"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(value: toml::de::Error) -> Self {
+        Self::Toml(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, ColorScheme, Error};
+    use crate::ppu::{PaletteRegister, Pixel};
+
+    #[test]
+    fn parse_custom_scheme() {
+        let schemes = ColorScheme::parse(
+            r##"
+            [[scheme]]
+            name = "Red"
+            bg = ["#FF0000", "#AA0000", "550000", "#000000"]
+            obj1 = ["#FFFFFF", "#00FF00", "#00AA00", "#005500"]
+            "##,
+        )
+        .expect("cannot parse");
+
+        let red = &schemes[0];
+        assert_eq!(red.name, "Red");
+        assert_eq!(red.obj0, red.bg);
+        let pixel = Pixel {
+            shade: 2,
+            palette: PaletteRegister::Bgp,
+        };
+        assert_eq!(red.color(pixel), [0x55, 0x00, 0x00]);
+        let pixel = Pixel {
+            shade: 1,
+            palette: PaletteRegister::Obp1,
+        };
+        assert_eq!(red.color(pixel), [0x00, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn parse_invalid_color() {
+        let err = ColorScheme::parse(
+            r##"
+            [[scheme]]
+            name = "Broken"
+            bg = ["#FF0000", "#AA00", "#550000", "#000000"]
+            "##,
+        );
+        assert!(matches!(err, Err(Error::Color(color)) if color == "#AA00"));
+    }
+
+    #[test]
+    fn find_preset() {
+        let presets = ColorScheme::presets();
+        assert_eq!(find(&presets, "grey"), Some(0));
+        assert_eq!(find(&presets, "pocket"), Some(2));
+        assert_eq!(find(&presets, "unknown"), None);
+    }
+}
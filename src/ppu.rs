@@ -3,25 +3,157 @@
 //!
 //! Start   End     Description                        Notes
 //! 8000    9FFF    8 KiB Video RAM (VRAM)             In CGB mode, switchable bank 0/1
+//! FE00    FE9F    Object attribute memory (OAM)
+//!
+//! Pixels are drawn either a whole scanline at a time or, with [`RenderMode::PixelFifo`], dot by
+//! dot through the background fetcher, the object fetcher and the pixel FIFOs.
+
+use std::{collections::VecDeque, fmt};
 
-use crate::{cpu::interrupt::Interrupt, sdl::Renderer};
+use crate::cpu::interrupt::Interrupt;
 
 /// VRAM size.
 pub const VRAM_SIZE: usize = 0x2000;
-/// OAM Memory location in VRAM.
-pub const VRAM_LY_OFFSET: usize = 0x0F44;
-/// OAM Memory location in VRAM.
-pub const VRAM_OAM_OFFSET: usize = 0x0E00;
-/// LCD Control register
-pub const VRAM_LCDC_OFFSET: usize = 0x0F40;
-/// WY Register Memory location in VRAM.
-pub const VRAM_WY_OFFSET: usize = 0x0F4A;
-/// WX Register Memory location in VRAM.
-pub const VRAM_WX_OFFSET: usize = 0x0F4B;
+/// OAM size.
+pub const OAM_SIZE: usize = OAM_SPRITES * OAM_SPRITE_SIZE;
+/// OAM offset in memory.
+pub const OAM_OFFSET: usize = 0xFE00;
+/// Number of sprites in OAM.
+const OAM_SPRITES: usize = 40;
+/// Bytes per sprite in OAM.
+const OAM_SPRITE_SIZE: usize = 4;
+/// Most sprites drawn on a single scanline.
+const SPRITES_PER_LINE: usize = 10;
+/// Sprite coordinates are offset so they can be partially off screen.
+const SPRITE_X_OFFSET: usize = 8;
+const SPRITE_Y_OFFSET: usize = 16;
 /// VRAM offset in WRAM.
 pub const VRAM_OFFSET: usize = 0x8000;
 
 pub const LY_VBLANK_START: u8 = 144;
+/// Scanlines per frame, including V-Blank.
+pub const LINES_PER_FRAME: u8 = 154;
+
+/// Dots per scanline.
+const DOTS_PER_LINE: u16 = 456;
+/// Dots spent in OAM search.
+const OAM_SEARCH_DOTS: u16 = 80;
+/// Dots spent in pixel transfer without any fetcher stalls.
+const PIXEL_TRANSFER_DOTS: u16 = 172;
+/// Dots the fetcher stalls switching to the window.
+const WINDOW_FETCH_DOTS: u16 = 6;
+/// Dots the fetcher stalls for every sprite.
+const SPRITE_FETCH_DOTS: u16 = 6;
+/// Dots the fetcher stalls for a sprite at x 0.
+const SPRITE_X_0_FETCH_DOTS: u16 = 11;
+/// Dots of the first tile fetch of a scanline, which is thrown away.
+const FIFO_STARTUP_DOTS: u8 = 6;
+/// Dot of a tile fetch the fetcher is ready to push the tile at, after reading the tile
+/// number and both data bytes in two dots each.
+const FETCH_PUSH_DOT: u8 = 6;
+
+/// Tile map offsets in VRAM, selected by LCDC bit 3 (background) and bit 6 (window).
+const VRAM_TILE_MAP_0_OFFSET: usize = 0x1800;
+const VRAM_TILE_MAP_1_OFFSET: usize = 0x1C00;
+/// WX of a window starting at the left edge of the screen.
+const WX_OFFSET: u8 = 7;
+/// Largest WX that still shows the window. At this value the window also spans the entire
+/// following scanline.
+const WX_MAX: u8 = 166;
+/// Base of the signed tile data addressing mode (LCDC bit 4 unset) in VRAM.
+const VRAM_TILE_DATA_SIGNED_OFFSET: usize = 0x1000;
+/// Tiles per tile map row.
+const TILE_MAP_WIDTH: usize = 32;
+/// Bytes per tile, 8 rows of two bytes.
+const TILE_SIZE: usize = 16;
+
+/// Writable STAT bits, the interrupt source selection.
+const STAT_INTERRUPT_SOURCES: u8 = 0b0111_1000;
+/// STAT bit set while LY equals LYC.
+const STAT_LYC_EQUAL: u8 = 0b0000_0100;
+/// STAT interrupt source bits.
+const STAT_HBLANK_INTERRUPT: u8 = 0b0000_1000;
+const STAT_VBLANK_INTERRUPT: u8 = 0b0001_0000;
+const STAT_OAM_INTERRUPT: u8 = 0b0010_0000;
+const STAT_LYC_INTERRUPT: u8 = 0b0100_0000;
+
+// LCD Registers
+const REGISTER_LCDC: u16 = 0xFF40;
+const REGISTER_STAT: u16 = 0xFF41;
+const REGISTER_SCY: u16 = 0xFF42;
+const REGISTER_SCX: u16 = 0xFF43;
+const REGISTER_LY: u16 = 0xFF44;
+const REGISTER_LYC: u16 = 0xFF45;
+const REGISTER_BGP: u16 = 0xFF47;
+const REGISTER_OBP0: u16 = 0xFF48;
+const REGISTER_OBP1: u16 = 0xFF49;
+const REGISTER_WY: u16 = 0xFF4A;
+const REGISTER_WX: u16 = 0xFF4B;
+
+/// Width of the LCD in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+/// Height of the LCD in pixels.
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// Palette register a pixel's shade was looked up in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PaletteRegister {
+    #[default]
+    Bgp,
+    Obp0,
+    Obp1,
+}
+
+/// A pixel on the LCD.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pixel {
+    /// Shade, 0 (lightest) to 3.
+    pub shade: u8,
+    /// Palette the shade came from, so frontends can colour layers separately.
+    pub palette: PaletteRegister,
+}
+
+/// Finished picture, one [`Pixel`] per pixel in row major order.
+#[derive(Clone, PartialEq, Eq)]
+pub struct FrameBuffer {
+    pixels: Box<[Pixel]>,
+}
+
+impl FrameBuffer {
+    /// Shade of the pixel at `x`, `y`.
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixel(x, y).shade
+    }
+
+    /// The pixel at `x`, `y`.
+    pub fn pixel(&self, x: usize, y: usize) -> Pixel {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    /// Set the pixel at `x`, `y`.
+    pub fn set(&mut self, x: usize, y: usize, pixel: Pixel) {
+        self.pixels[y * SCREEN_WIDTH + x] = pixel;
+    }
+
+    /// All pixels, row by row.
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self {
+            pixels: vec![Pixel::default(); SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice(),
+        }
+    }
+}
+
+impl fmt::Debug for FrameBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FrameBuffer({SCREEN_WIDTH}x{SCREEN_HEIGHT})")
+    }
+}
 
 /// PPU State. State cycles throughout operation and determines what the PPU does.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -36,26 +168,143 @@ pub enum State {
     VBlank,
 }
 
+impl State {
+    /// Mode number reported in STAT.
+    pub fn mode(self) -> u8 {
+        match self {
+            Self::HBlank => 0,
+            Self::VBlank => 1,
+            Self::OAMSearch => 2,
+            Self::PixelTransfer => 3,
+        }
+    }
+}
+
+/// How the ppu draws pixels during pixel transfer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Draw the whole scanline at the end of pixel transfer. Fast, but register writes during
+    /// pixel transfer only show up on the next scanline.
+    #[default]
+    Scanline,
+    /// Run the pixel fetchers and FIFOs dot by dot, so register writes during pixel transfer
+    /// take effect at the pixel being drawn.
+    PixelFifo,
+}
+
+/// A sprite pixel waiting in the object FIFO.
+#[derive(Debug, Clone, Copy, Default)]
+struct ObjPixel {
+    color: u8,
+    flags: SpriteFlags,
+}
+
+/// Pixel FIFO state of the current scanline.
+#[derive(Debug, Default)]
+struct PixelFifo {
+    /// Background and window color indices.
+    bg: VecDeque<u8>,
+    obj: VecDeque<ObjPixel>,
+    /// Dots left of the thrown away first tile fetch.
+    startup: u8,
+    /// Dots the background fetcher spent on the current tile.
+    fetch_dot: u8,
+    /// Tile column the background fetcher is at.
+    fetch_x: u8,
+    tile_index: u8,
+    data_lo: u8,
+    data_hi: u8,
+    /// Pixels left to throw away, for fine scrolling.
+    discard: u8,
+    /// Pixels pushed to the LCD.
+    lx: usize,
+    /// The fetcher switched to the window.
+    window: bool,
+    /// The window was drawn at WX 166 on the previous line and starts at the left edge.
+    window_wraps: bool,
+    /// Sprites on the scanline not fetched yet.
+    sprites: Vec<Sprite>,
+    /// Sprite being fetched and the dots left.
+    sprite_fetch: Option<(Sprite, u16)>,
+}
+
+impl PixelFifo {
+    /// Start a new scanline.
+    fn reset(&mut self, discard: u8, sprites: &[Sprite], window_wraps: bool) {
+        self.bg.clear();
+        self.obj.clear();
+        self.startup = FIFO_STARTUP_DOTS;
+        self.fetch_dot = 0;
+        self.fetch_x = 0;
+        self.discard = discard;
+        self.lx = 0;
+        self.window = false;
+        self.window_wraps = window_wraps;
+        self.sprites.clear();
+        self.sprites.extend_from_slice(sprites);
+        self.sprite_fetch = None;
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug)]
 pub struct Ppu {
     /// Video Memory.
     vram: [u8; VRAM_SIZE],
+    /// Object attribute memory.
+    oam: [u8; OAM_SIZE],
     /// PPU State.
     state: State,
     /// PPU LY
     ly: u8,
+    /// Dot within the current scanline.
+    dot: u16,
+    /// Length of pixel transfer on the current scanline.
+    pixel_transfer_dots: u16,
+    /// WX the window is drawn at on the current scanline.
+    line_window_x: Option<usize>,
 
-    previous_ly: u8,
+    /// LCD control.
+    lcdc: u8,
+    /// Selected STAT interrupt sources.
+    stat: u8,
+    /// LY compare.
+    lyc: u8,
+    /// The STAT interrupt line, all selected sources or'd together. The interrupt is only
+    /// requested when it goes high, so a source can't fire while another keeps it high.
+    stat_line: bool,
+    /// Background scroll.
+    scy: u8,
+    scx: u8,
+    /// Background palette.
+    bgp: u8,
+    /// Object palettes.
+    obp0: u8,
+    obp1: u8,
+    /// Window position.
+    wy: u8,
+    wx: u8,
+    /// Set once LY matched WY this frame, the window can only show up from then on.
+    window_y_triggered: bool,
+    /// Internal window line counter, only advances on lines the window was drawn on.
+    window_line: u8,
+    /// The window was drawn at WX 166 on the previous line and spans this whole line.
+    window_wraps: bool,
 
     /// Currently loaded sprites.
     sprite_buffer: Vec<Sprite>,
 
-    t_cycle: u16,
+    render_mode: RenderMode,
+    fifo: PixelFifo,
 
-    renderer: Renderer,
+    /// Picture drawn so far.
+    framebuffer: FrameBuffer,
+    /// Set when a frame was finished, cleared by [`Ppu::take_frame_ready`].
+    frame_ready: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, Default)]
 struct SpriteFlags {
     /// Object background priority.
     pub(self) obj_to_bg_priority: bool,
@@ -80,10 +329,10 @@ impl From<u8> for SpriteFlags {
 
 #[derive(Debug, Clone, Copy)]
 struct Sprite {
-    pub(self) x: i8,
-    pub(self) y: i8,
+    pub(self) x: u8,
+    pub(self) y: u8,
     pub(self) tile_number: u8,
-    pub(self) sprite_flags: SpriteFlags,
+    pub(self) flags: SpriteFlags,
 }
 
 impl Default for Sprite {
@@ -92,22 +341,47 @@ impl Default for Sprite {
             x: 0,
             y: 0,
             tile_number: 0,
-            sprite_flags: 0.into(),
+            flags: 0.into(),
         }
     }
 }
 
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ppu {
-    pub fn new(renderer: Renderer) -> Self {
+    pub fn new() -> Self {
         tracing::info!("initializing ppu");
         Self {
             vram: [0; VRAM_SIZE],
-            ly: 0x90,
-            previous_ly: 0x90,
-            t_cycle: 0,
+            oam: [0; OAM_SIZE],
+            ly: 0,
+            dot: 0,
+            pixel_transfer_dots: PIXEL_TRANSFER_DOTS,
+            line_window_x: None,
+            lcdc: 0,
+            stat: 0,
+            lyc: 0,
+            stat_line: false,
+            scy: 0,
+            scx: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            window_y_triggered: false,
+            window_line: 0,
+            window_wraps: false,
             state: State::OAMSearch,
             sprite_buffer: Vec::with_capacity(10),
-            renderer,
+            render_mode: RenderMode::default(),
+            fifo: PixelFifo::default(),
+            framebuffer: FrameBuffer::default(),
+            frame_ready: false,
         }
     }
 
@@ -115,8 +389,23 @@ impl Ppu {
         self.state
     }
 
+    /// Select how pixels are drawn.
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+    }
+
+    /// The current picture.
+    pub fn framebuffer(&self) -> &FrameBuffer {
+        &self.framebuffer
+    }
+
+    /// Returns whether a frame was finished since the last call.
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
     fn lcdc(&self) -> u8 {
-        self.vram[VRAM_LCDC_OFFSET]
+        self.lcdc
     }
 
     fn lcdc_display_enable(&self) -> bool {
@@ -143,6 +432,15 @@ impl Ppu {
         ((self.lcdc() >> 2) & 1) == 1
     }
 
+    /// Sprite height in pixels, 8 or 16.
+    fn sprite_height(&self) -> usize {
+        if self.lcdc_sprite_height() {
+            16
+        } else {
+            8
+        }
+    }
+
     fn lcdc_sprite_enable(&self) -> bool {
         ((self.lcdc() >> 1) & 1) == 1
     }
@@ -152,109 +450,948 @@ impl Ppu {
     }
 
     pub fn set_ly(&mut self, val: u8) {
-        self.previous_ly = self.ly;
         self.ly = val;
     }
 
-    /// Load a sprites information (not pixel data) from OAM Memory.
-    #[allow(clippy::cast_possible_wrap)]
-    fn oam_load_sprite(&self, sprite_position: u8) -> Option<Sprite> {
-        let sprite_address = VRAM_OAM_OFFSET + (sprite_position * 4) as usize;
+    /// Load a sprites information (not pixel data) from OAM Memory, if it is on the current
+    /// scanline.
+    fn oam_load_sprite(&self, sprite_position: usize) -> Option<Sprite> {
+        let sprite_address = sprite_position * OAM_SPRITE_SIZE;
         let sprite = Sprite {
-            y: self.vram[sprite_address] as i8,
-            x: self.vram[sprite_address + 1] as i8,
-            tile_number: self.vram[sprite_address + 2],
-            sprite_flags: self.vram[sprite_address + 3].into(),
+            y: self.oam[sprite_address],
+            x: self.oam[sprite_address + 1],
+            tile_number: self.oam[sprite_address + 2],
+            flags: self.oam[sprite_address + 3].into(),
         };
 
-        let sprite_height = if self.lcdc_sprite_height() { 16 } else { 8 };
-
-        // Check if the sprite is on screen.
-        if sprite.x > 0
-            && ((self.ly + 16) as i8) >= sprite.y
-            && ((self.ly + 16) as i8) < sprite.y + sprite_height
-            && self.sprite_buffer.len() <= 10
-        {
-            return Some(sprite);
-        }
-
-        None
+        // The x coordinate doesn't matter, sprites off screen horizontally still count.
+        let line = self.ly as usize + SPRITE_Y_OFFSET;
+        let top = sprite.y as usize;
+        (top <= line && line < top + self.sprite_height()).then_some(sprite)
     }
 
-    /// Perform the OAM scan, loading [`Sprite`] information into the `sprite_buffer`.
+    /// Perform the OAM scan, loading up to ten [`Sprite`]s on the current scanline into the
+    /// `sprite_buffer` in OAM order.
     fn oam_scan(&mut self) {
-        for i in 0..19 {
+        for i in 0..OAM_SPRITES {
+            if self.sprite_buffer.len() == SPRITES_PER_LINE {
+                break;
+            }
             if let Some(sprite) = self.oam_load_sprite(i) {
                 self.sprite_buffer.push(sprite);
             }
         }
     }
 
-    fn pixel_transfer(&mut self) {}
+    /// Highest priority opaque sprite pixel at screen column `x`, with its color index.
+    ///
+    /// The sprite with the smallest x coordinate wins, ties go to the one first in OAM.
+    fn sprite_pixel(&self, x: usize) -> Option<(Sprite, u8)> {
+        if !self.lcdc_sprite_enable() {
+            return None;
+        }
+        self.sprite_buffer
+            .iter()
+            .filter_map(|sprite| self.sprite_color(*sprite, x).map(|color| (*sprite, color)))
+            .min_by_key(|(sprite, _)| sprite.x)
+    }
+
+    /// Color index (1-3) of `sprite` at screen column `x` on the current scanline, `None` if
+    /// it doesn't cover `x` or is transparent there.
+    fn sprite_color(&self, sprite: Sprite, x: usize) -> Option<u8> {
+        let col = (x + SPRITE_X_OFFSET)
+            .checked_sub(sprite.x as usize)
+            .filter(|col| *col < 8)?;
+        let color = self.sprite_row_color(sprite, col);
+        (color != 0).then_some(color)
+    }
+
+    /// Color index (0-3) of `sprite` at column `col`, counted from its left edge on screen,
+    /// on the current scanline.
+    fn sprite_row_color(&self, sprite: Sprite, col: usize) -> u8 {
+        let col = if sprite.flags.x_flip { 7 - col } else { col };
+
+        let height = self.sprite_height();
+        let row = self.ly as usize + SPRITE_Y_OFFSET - sprite.y as usize;
+        let row = if sprite.flags.y_flip {
+            height - 1 - row
+        } else {
+            row
+        };
+        // In 8x16 mode the tiles are paired, ignoring the lowest bit of the tile number.
+        let tile_number = if height == 16 {
+            sprite.tile_number & 0xFE
+        } else {
+            sprite.tile_number
+        };
+
+        // Sprites always use the unsigned tile data addressing mode.
+        self.tile_row_color(tile_number as usize * TILE_SIZE + row * 2, col)
+    }
+
+    /// Mix a pixel, given the background color index (`None` if the background is disabled)
+    /// and the sprite pixel on top of it.
+    fn mix_pixel(&self, bg_color: Option<u8>, sprite: Option<(SpriteFlags, u8)>) -> Pixel {
+        let bg_color_index = bg_color.unwrap_or(0);
+        match sprite {
+            // Background colors 1-3 are drawn over sprites with the priority flag.
+            Some((flags, color)) if !(flags.obj_to_bg_priority && bg_color_index != 0) => {
+                let (palette, register) = if flags.palette_number {
+                    (self.obp1, PaletteRegister::Obp1)
+                } else {
+                    (self.obp0, PaletteRegister::Obp0)
+                };
+                Pixel {
+                    shade: Self::palette_shade(palette, color),
+                    palette: register,
+                }
+            }
+            _ => Pixel {
+                shade: bg_color.map_or(0, |color| Self::palette_shade(self.bgp, color)),
+                palette: PaletteRegister::Bgp,
+            },
+        }
+    }
+
+    /// Draw the current scanline into the framebuffer.
+    fn pixel_transfer(&mut self) {
+        let y = self.ly as usize;
+        let window_x = self.line_window_x;
+        for x in 0..SCREEN_WIDTH {
+            let bg_color = self.lcdc_bg_enable().then(|| match window_x {
+                Some(wx) if x + WX_OFFSET as usize >= wx => {
+                    self.window_color(x + WX_OFFSET as usize - wx)
+                }
+                _ => self.bg_color(x as u8),
+            });
+            let sprite = self
+                .sprite_pixel(x)
+                .map(|(sprite, color)| (sprite.flags, color));
+            let pixel = self.mix_pixel(bg_color, sprite);
+            self.framebuffer.set(x, y, pixel);
+        }
+        if window_x.is_some() {
+            self.window_line = self.window_line.wrapping_add(1);
+        }
+    }
+
+    /// Advance pixel transfer by one dot in [`RenderMode::PixelFifo`], entering H-Blank once
+    /// the scanline is finished.
+    fn fifo_dot(&mut self) {
+        if self.fifo.startup > 0 {
+            self.fifo.startup -= 1;
+            return;
+        }
+        if let Some((sprite, dots)) = self.fifo.sprite_fetch {
+            if dots > 1 {
+                self.fifo.sprite_fetch = Some((sprite, dots - 1));
+            } else {
+                self.fifo.sprite_fetch = None;
+                self.fifo_merge_sprite(sprite);
+            }
+            return;
+        }
+
+        self.fifo_check_window();
+        self.fifo_fetch();
+        if self.fifo.bg.is_empty() {
+            return;
+        }
+
+        if self.fifo.discard == 0 && self.lcdc_sprite_enable() {
+            let lx = self.fifo.lx;
+            let next_sprite = self
+                .fifo
+                .sprites
+                .iter()
+                .position(|sprite| sprite.x as usize <= lx + SPRITE_X_OFFSET);
+            if let Some(i) = next_sprite {
+                // The object fetch waits for the background fetcher to finish its tile.
+                if self.fifo.fetch_dot >= FETCH_PUSH_DOT {
+                    let sprite = self.fifo.sprites.remove(i);
+                    let dots = if sprite.x == 0 {
+                        SPRITE_X_0_FETCH_DOTS
+                    } else {
+                        SPRITE_FETCH_DOTS
+                    };
+                    // This dot already counts towards the fetch.
+                    self.fifo.sprite_fetch = Some((sprite, dots - 1));
+                }
+                return;
+            }
+        }
+
+        self.fifo_push_pixel();
+        if self.fifo.lx == SCREEN_WIDTH {
+            if self.fifo.window {
+                self.window_line = self.window_line.wrapping_add(1);
+            }
+            self.state = State::HBlank;
+        }
+    }
+
+    /// Switch the fetcher to the window, once the next pixel is at WX.
+    fn fifo_check_window(&mut self) {
+        if self.fifo.window || !self.lcdc_window_display_enable() || !self.window_y_triggered {
+            return;
+        }
+        let lx = self.fifo.lx + WX_OFFSET as usize;
+        let wx = self.wx as usize;
+        let wraps = self.fifo.lx == 0 && self.fifo.window_wraps;
+        let start_of_line = self.fifo.lx == 0 && wx < WX_OFFSET as usize;
+        if wx != lx && !start_of_line && !wraps {
+            return;
+        }
+        self.fifo.window = true;
+        self.fifo.bg.clear();
+        self.fifo.fetch_dot = 0;
+        self.fifo.fetch_x = 0;
+        if wraps {
+            self.fifo.discard = 0;
+        } else if self.fifo.lx == 0 {
+            // WX 0-6 cut off the leftmost window columns instead of the fine scrolled ones.
+            self.fifo.discard = WX_OFFSET - self.wx.min(WX_OFFSET);
+        } else if self.wx == WX_MAX {
+            // Started at the last pixel, the window spans the whole next line.
+            self.window_wraps = true;
+        }
+    }
+
+    /// Advance the background fetcher by one dot. Reading the tile number and both data bytes
+    /// takes two dots each, the tile is pushed as soon as the background FIFO is empty.
+    fn fifo_fetch(&mut self) {
+        match self.fifo.fetch_dot {
+            1 => self.fifo.tile_index = self.vram[self.fetch_tile_map_address()],
+            3 => self.fifo.data_lo = self.vram[self.fetch_tile_row_address()],
+            5 => self.fifo.data_hi = self.vram[self.fetch_tile_row_address() + 1],
+            FETCH_PUSH_DOT.. => {
+                if self.fifo.bg.is_empty() {
+                    let (lo, hi) = (self.fifo.data_lo, self.fifo.data_hi);
+                    self.fifo.bg.extend(
+                        (0..8)
+                            .rev()
+                            .map(|bit| (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)),
+                    );
+                    self.fifo.fetch_x = self.fifo.fetch_x.wrapping_add(1);
+                    self.fifo.fetch_dot = 0;
+                }
+                return;
+            }
+            _ => {}
+        }
+        self.fifo.fetch_dot += 1;
+    }
+
+    /// Tile map address of the tile the fetcher is at.
+    fn fetch_tile_map_address(&self) -> usize {
+        let (tile_map_1, x, y) = if self.fifo.window {
+            (
+                self.lcdc_window_tile_map_select(),
+                self.fifo.fetch_x as usize,
+                self.window_line as usize,
+            )
+        } else {
+            (
+                self.lcdc_bg_tile_map_select_mode(),
+                (self.scx / 8).wrapping_add(self.fifo.fetch_x) as usize,
+                self.ly.wrapping_add(self.scy) as usize,
+            )
+        };
+        let tile_map = if tile_map_1 {
+            VRAM_TILE_MAP_1_OFFSET
+        } else {
+            VRAM_TILE_MAP_0_OFFSET
+        };
+        tile_map + (y / 8) * TILE_MAP_WIDTH + x % TILE_MAP_WIDTH
+    }
+
+    /// Address of the row of the tile the fetcher is at.
+    fn fetch_tile_row_address(&self) -> usize {
+        let y = if self.fifo.window {
+            self.window_line
+        } else {
+            self.ly.wrapping_add(self.scy)
+        };
+        self.tile_address(self.fifo.tile_index) + (y as usize % 8) * 2
+    }
+
+    /// Mix a fetched sprite into the object FIFO. Pixels of sprites fetched earlier win, unless
+    /// they are transparent.
+    fn fifo_merge_sprite(&mut self, sprite: Sprite) {
+        for col in 0..8 {
+            // Pixels left of the screen are dropped.
+            let Some(offset) =
+                (sprite.x as usize + col).checked_sub(SPRITE_X_OFFSET + self.fifo.lx)
+            else {
+                continue;
+            };
+            let color = self.sprite_row_color(sprite, col);
+            if self.fifo.obj.len() <= offset {
+                self.fifo.obj.resize(offset + 1, ObjPixel::default());
+            }
+            let pixel = &mut self.fifo.obj[offset];
+            if pixel.color == 0 {
+                *pixel = ObjPixel {
+                    color,
+                    flags: sprite.flags,
+                };
+            }
+        }
+    }
+
+    /// Shift one pixel out of the FIFOs onto the LCD.
+    fn fifo_push_pixel(&mut self) {
+        let Some(bg_color) = self.fifo.bg.pop_front() else {
+            return;
+        };
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return;
+        }
+        let obj = self.fifo.obj.pop_front().unwrap_or_default();
+        let bg_color = self.lcdc_bg_enable().then_some(bg_color);
+        let sprite =
+            (obj.color != 0 && self.lcdc_sprite_enable()).then_some((obj.flags, obj.color));
+        let pixel = self.mix_pixel(bg_color, sprite);
+        self.framebuffer.set(self.fifo.lx, self.ly as usize, pixel);
+        self.fifo.lx += 1;
+    }
+
+    /// Length of pixel transfer on the current scanline. The fetcher stalls while it discards
+    /// the fine scrolled pixels, when it switches to the window and for every sprite, more so
+    /// for the first sprite on a background tile close to its left edge.
+    fn pixel_transfer_dots(&self) -> u16 {
+        let mut dots = PIXEL_TRANSFER_DOTS + u16::from(self.scx & 7);
+        if self.line_window_x.is_some() {
+            dots += WINDOW_FETCH_DOTS;
+        }
+        if !self.lcdc_sprite_enable() {
+            return dots;
+        }
+
+        let mut fetched_tiles = Vec::with_capacity(SPRITES_PER_LINE);
+        for sprite in &self.sprite_buffer {
+            let x = sprite.x as usize;
+            if x >= SCREEN_WIDTH + SPRITE_X_OFFSET {
+                continue;
+            }
+            if x == 0 {
+                dots += SPRITE_X_0_FETCH_DOTS;
+                continue;
+            }
+            let pixel = x + (self.scx & 7) as usize;
+            if !fetched_tiles.contains(&(pixel / 8)) {
+                fetched_tiles.push(pixel / 8);
+                dots += (7 - pixel % 8).saturating_sub(2) as u16;
+            }
+            dots += SPRITE_FETCH_DOTS;
+        }
+        dots
+    }
+
+    /// WX the window is drawn at on the current scanline, `None` if it isn't visible.
+    fn window_x(&mut self) -> Option<usize> {
+        let wraps = std::mem::take(&mut self.window_wraps);
+        if !self.lcdc_window_display_enable() || !self.window_y_triggered {
+            return None;
+        }
+        match self.wx {
+            _ if wraps => Some(WX_OFFSET as usize),
+            WX_MAX => {
+                self.window_wraps = true;
+                Some(WX_MAX as usize)
+            }
+            // WX 0-6 cut off the leftmost window columns.
+            0..WX_MAX => Some(self.wx as usize),
+            _ => None,
+        }
+    }
+
+    /// Color index (0-3) of the window at window column `x` on the current window line.
+    fn window_color(&self, x: usize) -> u8 {
+        let y = self.window_line as usize;
+        let tile_map = if self.lcdc_window_tile_map_select() {
+            VRAM_TILE_MAP_1_OFFSET
+        } else {
+            VRAM_TILE_MAP_0_OFFSET
+        };
+        let tile_index = self.vram[tile_map + (y / 8) * TILE_MAP_WIDTH + x / 8];
+        self.tile_color(tile_index, x % 8, y % 8)
+    }
+
+    /// Color index (0-3) of the background at screen column `x` on the current scanline.
+    fn bg_color(&self, x: u8) -> u8 {
+        let x = x.wrapping_add(self.scx) as usize;
+        let y = self.ly.wrapping_add(self.scy) as usize;
+        let tile_map = if self.lcdc_bg_tile_map_select_mode() {
+            VRAM_TILE_MAP_1_OFFSET
+        } else {
+            VRAM_TILE_MAP_0_OFFSET
+        };
+        let tile_index = self.vram[tile_map + (y / 8) * TILE_MAP_WIDTH + x / 8];
+        self.tile_color(tile_index, x % 8, y % 8)
+    }
+
+    /// Color index (0-3) of the pixel at `col`, `row` of a background or window tile.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn tile_color(&self, tile_index: u8, col: usize, row: usize) -> u8 {
+        self.tile_row_color(self.tile_address(tile_index) + row * 2, col)
+    }
+
+    /// VRAM address of a background or window tile, depending on the addressing mode.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn tile_address(&self, tile_index: u8) -> usize {
+        if self.lcdc_tile_data_select_mode() {
+            tile_index as usize * TILE_SIZE
+        } else {
+            let offset = isize::from(tile_index as i8) * TILE_SIZE as isize;
+            VRAM_TILE_DATA_SIGNED_OFFSET.wrapping_add_signed(offset)
+        }
+    }
+
+    /// Color index (0-3) of the pixel at `col` of the tile row at `row_address` in VRAM.
+    fn tile_row_color(&self, row_address: usize, col: usize) -> u8 {
+        let lo = self.vram[row_address];
+        let hi = self.vram[row_address + 1];
+        let bit = 7 - col;
+        (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1)
+    }
+
+    /// Shade of color index `color` in `palette`.
+    fn palette_shade(palette: u8, color: u8) -> u8 {
+        (palette >> (color * 2)) & 0b11
+    }
+
+    /// Current scanline.
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    /// STAT register, bit 7 is unused and always set.
+    fn stat(&self) -> u8 {
+        let lyc_equal = if self.ly == self.lyc {
+            STAT_LYC_EQUAL
+        } else {
+            0
+        };
+        0b1000_0000 | self.stat | lyc_equal | self.state.mode()
+    }
+
+    /// Level of the STAT interrupt line.
+    fn stat_line(&self) -> bool {
+        let stat = self.stat();
+        let source = match self.state {
+            State::HBlank => STAT_HBLANK_INTERRUPT,
+            State::VBlank => STAT_VBLANK_INTERRUPT,
+            State::OAMSearch => STAT_OAM_INTERRUPT,
+            State::PixelTransfer => 0,
+        };
+        stat & source != 0 || (stat & STAT_LYC_EQUAL != 0 && stat & STAT_LYC_INTERRUPT != 0)
+    }
+
+    /// Reads a LCD register.
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            REGISTER_LCDC => self.lcdc,
+            REGISTER_STAT => self.stat(),
+            REGISTER_SCY => self.scy,
+            REGISTER_SCX => self.scx,
+            REGISTER_LY => self.ly,
+            REGISTER_LYC => self.lyc,
+            REGISTER_BGP => self.bgp,
+            REGISTER_OBP0 => self.obp0,
+            REGISTER_OBP1 => self.obp1,
+            REGISTER_WY => self.wy,
+            REGISTER_WX => self.wx,
+            _ => panic!("unsupported lcd register read at 0x{address:x}"),
+        }
+    }
+
+    /// Writes a LCD register.
+    pub fn write_register(&mut self, address: u16, val: u8) {
+        match address {
+            REGISTER_LCDC => {
+                let display_enable = self.lcdc_display_enable();
+                self.lcdc = val;
+                match (display_enable, self.lcdc_display_enable()) {
+                    (true, false) => self.lcd_off(),
+                    (false, true) => self.lcd_on(),
+                    _ => {}
+                }
+            }
+            REGISTER_STAT => self.stat = val & STAT_INTERRUPT_SOURCES,
+            REGISTER_SCY => self.scy = val,
+            REGISTER_SCX => self.scx = val,
+            // Read only.
+            REGISTER_LY => (),
+            REGISTER_LYC => self.lyc = val,
+            REGISTER_BGP => self.bgp = val,
+            REGISTER_OBP0 => self.obp0 = val,
+            REGISTER_OBP1 => self.obp1 = val,
+            REGISTER_WY => self.wy = val,
+            REGISTER_WX => self.wx = val,
+            _ => panic!("unsupported lcd register write at 0x{address:x}"),
+        }
+    }
 
-    /// Reads from vram at address.
+    /// Stop the ppu. LY reads 0, STAT mode 0 and the screen goes blank.
+    fn lcd_off(&mut self) {
+        tracing::debug!("lcd off");
+        self.ly = 0;
+        self.dot = 0;
+        self.state = State::HBlank;
+        self.stat_line = false;
+        self.sprite_buffer.clear();
+        self.framebuffer = FrameBuffer::default();
+    }
+
+    /// Restart the ppu at the beginning of a frame.
+    fn lcd_on(&mut self) {
+        tracing::debug!("lcd on");
+        self.ly = 0;
+        self.dot = 0;
+        self.state = State::OAMSearch;
+        self.window_y_triggered = false;
+        self.window_line = 0;
+        self.window_wraps = false;
+    }
+
+    /// Whether the cpu can access VRAM or OAM at `address`. VRAM is locked while pixels are
+    /// drawn, OAM while the ppu scans it or draws sprites, unless the LCD is off.
+    pub fn cpu_accessible(&self, address: u16) -> bool {
+        if !self.lcdc_display_enable() {
+            return true;
+        }
+        match address {
+            0x8000..=0x9FFF => self.state != State::PixelTransfer,
+            0xFE00..=0xFE9F => !matches!(self.state, State::OAMSearch | State::PixelTransfer),
+            _ => true,
+        }
+    }
+
+    /// Writes OAM at `index`, used by OAM DMA.
+    pub fn write_oam(&mut self, index: u8, val: u8) {
+        self.oam[index as usize] = val;
+    }
+
+    /// Reads from vram or oam at address.
     pub fn read_u8(&self, address: u16) -> u8 {
-        let u_addr = (address as usize) - VRAM_OFFSET;
+        let u_addr = address as usize;
 
         match u_addr {
-            VRAM_LY_OFFSET => 0x90,
-            0x0000..=0x1FFF => self.vram[u_addr],
-            _ => panic!("unsupported vram read access at 0x{u_addr:x}, was 0x{address:x}"),
+            0x8000..=0x9FFF => self.vram[u_addr - VRAM_OFFSET],
+            0xFE00..=0xFE9F => self.oam[u_addr - OAM_OFFSET],
+            _ => panic!("unsupported vram read access at 0x{address:x}"),
         }
     }
 
-    /// Writes u8 to vram at address.
+    /// Writes u8 to vram or oam at address.
     pub fn write_u8(&mut self, address: u16, val: u8) {
-        let u_addr = (address as usize) - VRAM_OFFSET;
+        let u_addr = address as usize;
         match u_addr {
-            0x0000..=0x1FFF => self.vram[u_addr] = val,
-            _ => panic!("unsupported vram write access at 0x{u_addr:x}, was 0x{address:x}"),
+            0x8000..=0x9FFF => self.vram[u_addr - VRAM_OFFSET] = val,
+            0xFE00..=0xFE9F => self.oam[u_addr - OAM_OFFSET] = val,
+            _ => panic!("unsupported vram write access at 0x{address:x}"),
         }
     }
 
-    /// Cycle the mmu, returning the resulting state.
+    /// Advance the ppu by one dot, returning the requested interrupts.
     #[tracing::instrument(skip(self) fields(sprites_loaded=%self.sprite_buffer.len()))]
     pub fn cycle(&mut self) -> Vec<Interrupt> {
         let mut interrupts = Vec::with_capacity(1);
+        if !self.lcdc_display_enable() {
+            return interrupts;
+        }
+
+        self.dot += 1;
         match self.state {
-            State::OAMSearch => {
-                tracing::trace!("performing orm search");
-                if self.t_cycle % 80 == 0 {
-                    self.oam_scan();
-                    self.state = State::PixelTransfer;
+            State::OAMSearch if self.dot == OAM_SEARCH_DOTS => {
+                tracing::trace!("performing oam search");
+                self.oam_scan();
+                self.window_y_triggered |= self.ly == self.wy;
+                if self.render_mode == RenderMode::PixelFifo {
+                    let window_wraps = std::mem::take(&mut self.window_wraps);
+                    self.fifo
+                        .reset(self.scx & 7, &self.sprite_buffer, window_wraps);
+                } else {
+                    self.line_window_x = self.window_x();
+                    self.pixel_transfer_dots = self.pixel_transfer_dots();
                 }
+                self.state = State::PixelTransfer;
             }
-            State::PixelTransfer => {
+            State::PixelTransfer if self.render_mode == RenderMode::PixelFifo => self.fifo_dot(),
+            State::PixelTransfer if self.dot == OAM_SEARCH_DOTS + self.pixel_transfer_dots => {
                 tracing::trace!("performing pixel transfer");
                 self.pixel_transfer();
                 self.state = State::HBlank;
             }
-            State::HBlank => {
-                //tracing::trace!("performing horizontal blanks");
-                if self.t_cycle % 456 == 0 {
-                    self.ly += 1;
-                    self.sprite_buffer.resize(0, Sprite::default());
-                    if self.ly >= LY_VBLANK_START {
-                        self.state = State::VBlank;
-                    } else {
-                        self.state = State::OAMSearch;
-                    }
-                }
-            }
-            State::VBlank => {
-                tracing::trace!("horizontal vertical blanks");
-                if self.t_cycle % 4560 == 0 {
+            State::HBlank | State::VBlank if self.dot == DOTS_PER_LINE => {
+                self.dot = 0;
+                self.ly += 1;
+                self.sprite_buffer.clear();
+                if self.ly == LY_VBLANK_START {
+                    self.window_y_triggered = false;
+                    self.window_line = 0;
+                    self.window_wraps = false;
+                    self.state = State::VBlank;
+                    self.frame_ready = true;
+                    interrupts.push(Interrupt::VBlank);
+                } else if self.ly == LINES_PER_FRAME {
                     self.ly = 0;
                     self.state = State::OAMSearch;
+                } else if self.ly < LY_VBLANK_START {
+                    self.state = State::OAMSearch;
                 }
             }
-        };
-        self.t_cycle = self.t_cycle.wrapping_add(1);
+            _ => {}
+        }
 
-        if self.ly == LY_VBLANK_START && self.ly != self.previous_ly {
-            interrupts.push(Interrupt::VBlank);
+        let stat_line = self.stat_line();
+        if stat_line && !self.stat_line {
+            interrupts.push(Interrupt::LCD);
         }
+        self.stat_line = stat_line;
 
         interrupts
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Ppu, RenderMode};
+    use crate::cpu::interrupt::Interrupt;
+
+    fn render_frame(ppu: &mut Ppu) {
+        while !ppu.take_frame_ready() {
+            ppu.cycle();
+        }
+    }
+
+    fn render_line(ppu: &mut Ppu) {
+        for _ in 0..456 {
+            ppu.cycle();
+        }
+    }
+
+    fn write_sprite(ppu: &mut Ppu, index: u16, sprite: [u8; 4]) {
+        for (i, byte) in sprite.into_iter().enumerate() {
+            ppu.write_u8(0xFE00 + index * 4 + i as u16, byte);
+        }
+    }
+
+    /// Ppu with sprites enabled and a solid color 3 tile 1.
+    fn sprite_ppu() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0x93);
+        ppu.write_register(0xFF47, 0xE4);
+        ppu.write_register(0xFF48, 0xE4);
+        ppu.write_register(0xFF49, 0x54);
+        for address in 0x8010..0x8020 {
+            ppu.write_u8(address, 0xFF);
+        }
+        ppu
+    }
+
+    /// Ppu with the window enabled on map 0x9C00 and a solid color 3 tile 1.
+    fn window_ppu() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0xF1);
+        ppu.write_register(0xFF47, 0xE4);
+        for address in 0x8010..0x8020 {
+            ppu.write_u8(address, 0xFF);
+        }
+        ppu
+    }
+
+    #[test]
+    fn background_scrolls_and_wraps() {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0x91);
+        ppu.write_register(0xFF47, 0xE4);
+        // Tile 1, first row color 1, second row color 3.
+        ppu.write_u8(0x8010, 0xFF);
+        ppu.write_u8(0x8012, 0xFF);
+        ppu.write_u8(0x8013, 0xFF);
+        ppu.write_u8(0x9800, 0x01);
+        ppu.write_u8(0x981F, 0x01);
+
+        ppu.write_register(0xFF42, 0xFF);
+        ppu.write_register(0xFF43, 0xFC);
+        render_frame(&mut ppu);
+
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer.get(0, 0), 0);
+        // Last column of the map wraps around to the first one.
+        assert_eq!(framebuffer.get(0, 1), 1);
+        assert_eq!(framebuffer.get(11, 1), 1);
+        assert_eq!(framebuffer.get(12, 1), 0);
+        assert_eq!(framebuffer.get(0, 2), 3);
+    }
+
+    #[test]
+    fn background_signed_tile_data_and_second_map() {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0x89);
+        ppu.write_register(0xFF47, 0x1B);
+        // Tile -1 sits right below 0x9000.
+        ppu.write_u8(0x8FF1, 0x80);
+        ppu.write_u8(0x9C00, 0xFF);
+        render_frame(&mut ppu);
+
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer.get(0, 0), 1);
+        assert_eq!(framebuffer.get(1, 0), 3);
+    }
+
+    #[test]
+    fn window_line_counter_only_advances_when_drawn() {
+        let mut ppu = window_ppu();
+        ppu.write_register(0xFF4B, 7);
+        // Second row of window tiles.
+        ppu.write_u8(0x9C20, 0x01);
+        for _ in 0..4 {
+            render_line(&mut ppu);
+        }
+        ppu.write_register(0xFF40, 0xD1);
+        for _ in 0..8 {
+            render_line(&mut ppu);
+        }
+        ppu.write_register(0xFF40, 0xF1);
+        render_frame(&mut ppu);
+
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer.get(0, 8), 0);
+        assert_eq!(framebuffer.get(0, 15), 0);
+        assert_eq!(framebuffer.get(0, 16), 3);
+    }
+
+    #[test]
+    fn window_wx_below_7_cuts_off_left_columns() {
+        let mut ppu = window_ppu();
+        ppu.write_register(0xFF4B, 3);
+        ppu.write_u8(0x9C01, 0x01);
+        render_line(&mut ppu);
+
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer.get(3, 0), 0);
+        assert_eq!(framebuffer.get(4, 0), 3);
+    }
+
+    #[test]
+    fn window_wx_166_spans_following_line() {
+        for mode in [RenderMode::Scanline, RenderMode::PixelFifo] {
+            let mut ppu = window_ppu();
+            ppu.set_render_mode(mode);
+            for address in 0x9C00..0x9C20 {
+                ppu.write_u8(address, 0x01);
+            }
+            ppu.write_register(0xFF4B, 166);
+            render_line(&mut ppu);
+            ppu.write_register(0xFF4B, 200);
+            render_line(&mut ppu);
+            render_line(&mut ppu);
+
+            let framebuffer = ppu.framebuffer();
+            assert_eq!(framebuffer.get(158, 0), 0, "{mode:?}");
+            assert_eq!(framebuffer.get(159, 0), 3, "{mode:?}");
+            assert_eq!(framebuffer.get(0, 1), 3, "{mode:?}");
+            assert_eq!(framebuffer.get(159, 1), 3, "{mode:?}");
+            assert_eq!(framebuffer.get(0, 2), 0, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn sprites_limited_to_ten_per_line() {
+        let mut ppu = sprite_ppu();
+        for i in 0..11 {
+            write_sprite(&mut ppu, i, [16, 8 + i as u8 * 8, 1, 0]);
+        }
+        // Off screen horizontally, but still takes a slot.
+        write_sprite(&mut ppu, 0, [16, 0, 1, 0]);
+        render_line(&mut ppu);
+
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer.get(0, 0), 0);
+        assert_eq!(framebuffer.get(8, 0), 3);
+        assert_eq!(framebuffer.get(79, 0), 3);
+        assert_eq!(framebuffer.get(80, 0), 0);
+    }
+
+    #[test]
+    fn sprite_8x16_flips() {
+        let mut ppu = sprite_ppu();
+        ppu.write_register(0xFF40, 0x97);
+        // Last row of tile 3, leftmost pixel.
+        ppu.write_u8(0x803E, 0x80);
+        write_sprite(&mut ppu, 0, [16, 8, 2, 0x40]);
+        write_sprite(&mut ppu, 1, [16, 16, 3, 0x60]);
+        render_line(&mut ppu);
+
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer.get(0, 0), 1);
+        assert_eq!(framebuffer.get(1, 0), 0);
+        assert_eq!(framebuffer.get(15, 0), 1);
+    }
+
+    #[test]
+    fn sprite_priority() {
+        let mut ppu = sprite_ppu();
+        // Background tile 1 in the third column.
+        ppu.write_u8(0x9802, 0x01);
+        ppu.write_register(0xFF47, 0x00);
+        write_sprite(&mut ppu, 0, [16, 12, 1, 0x10]);
+        write_sprite(&mut ppu, 1, [16, 8, 1, 0x00]);
+        write_sprite(&mut ppu, 2, [16, 24, 1, 0x90]);
+        write_sprite(&mut ppu, 3, [16, 24, 1, 0x00]);
+        write_sprite(&mut ppu, 4, [16, 40, 1, 0x80]);
+        render_line(&mut ppu);
+
+        let framebuffer = ppu.framebuffer();
+        // Smaller x wins.
+        assert_eq!(framebuffer.get(4, 0), 3);
+        assert_eq!(framebuffer.get(8, 0), 1);
+        // Same x, first in OAM wins and is hidden behind the background.
+        assert_eq!(framebuffer.get(16, 0), 0);
+        // Background color 0 never hides sprites.
+        assert_eq!(framebuffer.get(32, 0), 3);
+    }
+
+    /// Runs `dots` dots, returning how many STAT interrupts were requested.
+    fn stat_interrupts(ppu: &mut Ppu, dots: usize) -> usize {
+        (0..dots)
+            .flat_map(|_| ppu.cycle())
+            .filter(|interrupt| matches!(interrupt, Interrupt::LCD))
+            .count()
+    }
+
+    #[test]
+    fn stat_reports_mode_and_lyc() {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0x80);
+        ppu.write_register(0xFF45, 0x01);
+        ppu.write_register(0xFF41, 0xFF);
+        assert_eq!(ppu.read_register(0xFF41), 0xFA);
+        ppu.cycle();
+        assert_eq!(ppu.read_register(0xFF41) & 0b111, 2);
+        stat_interrupts(&mut ppu, 79);
+        assert_eq!(ppu.read_register(0xFF41) & 0b111, 3);
+        stat_interrupts(&mut ppu, 172);
+        assert_eq!(ppu.read_register(0xFF41) & 0b111, 0);
+        stat_interrupts(&mut ppu, 204);
+        assert_eq!(ppu.read_register(0xFF44), 1);
+        assert_eq!(ppu.read_register(0xFF41) & 0b111, 0b110);
+    }
+
+    #[test]
+    fn stat_interrupt_line_blocks_back_to_back_sources() {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0x80);
+        ppu.write_register(0xFF45, 0x01);
+        ppu.write_register(0xFF41, 0x48);
+        assert_eq!(stat_interrupts(&mut ppu, 456), 1);
+        // LYC goes high as H-Blank ends, then keeps the line high through H-Blank.
+        assert_eq!(stat_interrupts(&mut ppu, 456), 0);
+        assert_eq!(stat_interrupts(&mut ppu, 456), 1);
+    }
+
+    #[test]
+    fn pixel_transfer_length_depends_on_fine_scroll_and_sprites() {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0x82);
+        ppu.write_register(0xFF43, 0x03);
+        // Leftmost pixel at the right edge of its background tile, the tile fetch doesn't stall.
+        write_sprite(&mut ppu, 0, [16, 20, 0, 0]);
+        for _ in 0..80 + 180 {
+            ppu.cycle();
+        }
+        assert_eq!(ppu.read_register(0xFF41) & 0b11, 3);
+        ppu.cycle();
+        assert_eq!(ppu.read_register(0xFF41) & 0b11, 0);
+        // H-Blank pads the line to 456 dots.
+        for _ in 0..456 - 80 - 181 {
+            ppu.cycle();
+        }
+        assert_eq!(ppu.read_register(0xFF44), 1);
+    }
+
+    #[test]
+    fn frame_takes_154_lines() {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0x80);
+        render_frame(&mut ppu);
+        let mut dots = 0;
+        while !ppu.take_frame_ready() {
+            ppu.cycle();
+            dots += 1;
+        }
+        assert_eq!(dots, 154 * 456);
+    }
+
+    #[test]
+    fn lcd_off_resets_and_stops_the_ppu() {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0x80);
+        for _ in 0..1000 {
+            ppu.cycle();
+        }
+        ppu.write_register(0xFF40, 0x00);
+        assert_eq!(ppu.read_register(0xFF44), 0);
+        assert_eq!(ppu.read_register(0xFF41) & 0b11, 0);
+        for _ in 0..70224 {
+            assert!(ppu.cycle().is_empty());
+        }
+        assert_eq!(ppu.read_register(0xFF44), 0);
+
+        ppu.write_register(0xFF40, 0x80);
+        ppu.cycle();
+        assert_eq!(ppu.read_register(0xFF41) & 0b11, 2);
+    }
+
+    #[test]
+    fn pixel_fifo_matches_scanline_renderer() {
+        let render = |render_mode| {
+            let mut ppu = sprite_ppu();
+            ppu.set_render_mode(render_mode);
+            ppu.write_register(0xFF40, 0xF3);
+            ppu.write_register(0xFF43, 0x05);
+            ppu.write_register(0xFF4A, 0x20);
+            ppu.write_register(0xFF4B, 0x30);
+            for address in 0x8020..0x8030 {
+                ppu.write_u8(address, 0x5A);
+            }
+            for i in 0..0x400 {
+                ppu.write_u8(0x9800 + i, (i % 3) as u8);
+                ppu.write_u8(0x9C00 + i, (i % 2) as u8 * 2);
+            }
+            write_sprite(&mut ppu, 0, [20, 4, 2, 0x20]);
+            write_sprite(&mut ppu, 1, [24, 50, 1, 0x90]);
+            write_sprite(&mut ppu, 2, [40, 50, 2, 0x50]);
+            write_sprite(&mut ppu, 3, [60, 164, 1, 0x00]);
+            render_frame(&mut ppu);
+            ppu.framebuffer().pixels().to_vec()
+        };
+        assert_eq!(render(RenderMode::PixelFifo), render(RenderMode::Scanline));
+    }
+
+    #[test]
+    fn pixel_fifo_applies_palette_writes_mid_scanline() {
+        let mut ppu = sprite_ppu();
+        ppu.set_render_mode(RenderMode::PixelFifo);
+        for i in 0..0x20 {
+            ppu.write_u8(0x9800 + i, 0x01);
+        }
+        for dot in 1..=80 + 172 {
+            // After a thrown away fetch and the first tile's fetch, pixel x is drawn on dot
+            // 93 + x.
+            if dot == 93 + 50 {
+                ppu.write_register(0xFF47, 0x00);
+            }
+            ppu.cycle();
+        }
+        assert_eq!(ppu.read_register(0xFF41) & 0b11, 0);
+
+        let framebuffer = ppu.framebuffer();
+        assert_eq!(framebuffer.get(49, 0), 3);
+        assert_eq!(framebuffer.get(50, 0), 0);
+    }
+}
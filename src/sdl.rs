@@ -1,12 +1,33 @@
 use std::fmt::Debug;
 
-use sdl2::Sdl;
+use rustboy::{
+    apu::SAMPLE_RATE,
+    frontend::{AudioSink, Button, Frontend, Input},
+    palette::ColorScheme,
+    ppu::{FrameBuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+use sdl2::{
+    audio::{AudioQueue, AudioSpecDesired},
+    event::Event,
+    keyboard::Keycode,
+    pixels::PixelFormatEnum,
+    rect::Rect,
+    render::Texture,
+    EventPump, Sdl,
+};
 
+#[allow(clippy::struct_field_names)]
 #[derive(Clone, Debug)]
 pub struct Config {
     pub window_width: u32,
     pub window_height: u32,
     pub window_title: String,
+    /// Colour schemes cycled through with `P`, falls back to the default one if empty.
+    pub color_schemes: Vec<ColorScheme>,
+    /// Index of the scheme in use.
+    pub color_scheme: usize,
+    /// Block presenting frames until display vsync.
+    pub vsync: bool,
 }
 
 impl Default for Config {
@@ -15,6 +36,9 @@ impl Default for Config {
             window_width: 800,
             window_height: 600,
             window_title: "Rustboy GB Emulator".into(),
+            color_schemes: ColorScheme::presets(),
+            color_scheme: 0,
+            vsync: false,
         }
     }
 }
@@ -22,6 +46,9 @@ impl Default for Config {
 pub struct Renderer {
     cfg: Config,
     canvas: sdl2::render::WindowCanvas,
+    /// Streaming texture the framebuffer is uploaded to, freed along with the canvas.
+    texture: Texture,
+    event_pump: EventPump,
 }
 
 impl Debug for Renderer {
@@ -58,16 +85,166 @@ impl From<String> for Error {
 }
 
 impl Renderer {
-    pub fn new(cfg: Config, sdl_ctx: &Sdl) -> Result<Self, Error> {
+    pub fn new(mut cfg: Config, sdl_ctx: &Sdl) -> Result<Self, Error> {
+        if cfg.color_schemes.is_empty() {
+            cfg.color_schemes.push(ColorScheme::default());
+        }
+        if cfg.color_scheme >= cfg.color_schemes.len() {
+            cfg.color_scheme = 0;
+        }
         let video_subsystem = sdl_ctx.video()?;
         let window = video_subsystem
             .window(&cfg.window_title, cfg.window_width, cfg.window_height)
             .build()?;
-        let mut canvas = window.into_canvas().build()?;
+        let mut canvas = if cfg.vsync {
+            window.into_canvas().present_vsync().build()?
+        } else {
+            window.into_canvas().build()?
+        };
+        let event_pump = sdl_ctx.event_pump()?;
+        let texture = canvas
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                SCREEN_WIDTH as u32,
+                SCREEN_HEIGHT as u32,
+            )
+            .map_err(|err| Error::SDL(err.to_string()))?;
 
         canvas.clear();
         canvas.present();
 
-        Ok(Self { cfg, canvas })
+        Ok(Self {
+            cfg,
+            canvas,
+            texture,
+            event_pump,
+        })
+    }
+
+    /// Draw the framebuffer, scaled by the largest integer factor that fits the window and
+    /// centered.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn draw(&mut self, framebuffer: &FrameBuffer) -> Result<(), Error> {
+        let scheme = &self.cfg.color_schemes[self.cfg.color_scheme];
+        let pixels: Vec<u8> = framebuffer
+            .pixels()
+            .iter()
+            .flat_map(|pixel| scheme.color(*pixel))
+            .collect();
+        self.texture
+            .update(None, &pixels, SCREEN_WIDTH * 3)
+            .map_err(|err| Error::SDL(err.to_string()))?;
+
+        let (width, height) = self.canvas.output_size()?;
+        let scale = (width / SCREEN_WIDTH as u32)
+            .min(height / SCREEN_HEIGHT as u32)
+            .max(1);
+        let (scaled_width, scaled_height) =
+            (SCREEN_WIDTH as u32 * scale, SCREEN_HEIGHT as u32 * scale);
+        let target = Rect::new(
+            (width.saturating_sub(scaled_width) / 2) as i32,
+            (height.saturating_sub(scaled_height) / 2) as i32,
+            scaled_width,
+            scaled_height,
+        );
+
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, target)?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    /// Switch to the next colour scheme.
+    fn next_color_scheme(&mut self) {
+        self.cfg.color_scheme = (self.cfg.color_scheme + 1) % self.cfg.color_schemes.len();
+        let scheme = &self.cfg.color_schemes[self.cfg.color_scheme];
+        tracing::info!(scheme = scheme.name, "switched colour scheme");
+    }
+}
+
+impl Frontend for Renderer {
+    fn present(&mut self, framebuffer: &FrameBuffer) {
+        if let Err(err) = self.draw(framebuffer) {
+            tracing::error!(?err, "failed to present frame");
+        }
+    }
+
+    fn poll_input(&mut self) -> Vec<Input> {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Quit { .. } => Some(Input::Quit),
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    repeat: false,
+                    ..
+                } => {
+                    self.next_color_scheme();
+                    None
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => button(keycode).map(Input::ButtonDown),
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => button(keycode).map(Input::ButtonUp),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Plays samples through an SDL audio queue.
+pub struct Audio {
+    queue: AudioQueue<f32>,
+}
+
+impl Debug for Audio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Audio {{ queued: {} }}", self.queue.size())
+    }
+}
+
+impl Audio {
+    pub fn new(sdl_ctx: &Sdl) -> Result<Self, Error> {
+        let desired = AudioSpecDesired {
+            freq: i32::try_from(SAMPLE_RATE).ok(),
+            channels: Some(2),
+            samples: Some(1024),
+        };
+        let queue = sdl_ctx.audio()?.open_queue::<f32, _>(None, &desired)?;
+        queue.resume();
+        Ok(Self { queue })
+    }
+}
+
+impl AudioSink for Audio {
+    fn queue(&mut self, samples: &[f32]) {
+        if let Err(err) = self.queue.queue_audio(samples) {
+            tracing::error!(?err, "failed to queue audio");
+        }
+    }
+
+    fn queued(&self) -> Option<usize> {
+        Some(self.queue.size() as usize / std::mem::size_of::<f32>())
+    }
+}
+
+/// Joypad button mapped to a key.
+fn button(keycode: Keycode) -> Option<Button> {
+    match keycode {
+        Keycode::Right => Some(Button::Right),
+        Keycode::Left => Some(Button::Left),
+        Keycode::Up => Some(Button::Up),
+        Keycode::Down => Some(Button::Down),
+        Keycode::X => Some(Button::A),
+        Keycode::Z => Some(Button::B),
+        Keycode::Backspace => Some(Button::Select),
+        Keycode::Return => Some(Button::Start),
+        _ => None,
     }
 }
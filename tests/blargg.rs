@@ -0,0 +1,55 @@
+//! Blargg's test ROMs, skipped if the ROMs aren't present under `files/`.
+
+mod common;
+
+use rustboy::harness::{blargg, Outcome};
+
+fn run(path: &str) {
+    let Some(mut gb) = common::load(&format!("blargg-test-roms/{path}")) else {
+        return;
+    };
+    let report = blargg::run(&mut gb, blargg::DEFAULT_TIMEOUT_FRAMES);
+    assert_eq!(report.outcome, Outcome::Passed, "{}", report.output);
+}
+
+macro_rules! blargg_tests {
+    ($($name:ident => $path:literal,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                run($path);
+            }
+        )*
+    };
+}
+
+blargg_tests! {
+    cpu_instrs_01_special => "cpu_instrs/individual/01-special.gb",
+    cpu_instrs_02_interrupts => "cpu_instrs/individual/02-interrupts.gb",
+    cpu_instrs_03_op_sp_hl => "cpu_instrs/individual/03-op sp,hl.gb",
+    cpu_instrs_04_op_r_imm => "cpu_instrs/individual/04-op r,imm.gb",
+    cpu_instrs_05_op_rp => "cpu_instrs/individual/05-op rp.gb",
+    cpu_instrs_06_ld_r_r => "cpu_instrs/individual/06-ld r,r.gb",
+    cpu_instrs_07_jr_jp_call_ret_rst => "cpu_instrs/individual/07-jr,jp,call,ret,rst.gb",
+    cpu_instrs_08_misc_instrs => "cpu_instrs/individual/08-misc instrs.gb",
+    cpu_instrs_09_op_r_r => "cpu_instrs/individual/09-op r,r.gb",
+    cpu_instrs_10_bit_ops => "cpu_instrs/individual/10-bit ops.gb",
+    cpu_instrs_11_op_a_hl => "cpu_instrs/individual/11-op a,(hl).gb",
+    instr_timing => "instr_timing/instr_timing.gb",
+    mem_timing_01_read_timing => "mem_timing/individual/01-read_timing.gb",
+    mem_timing_02_write_timing => "mem_timing/individual/02-write_timing.gb",
+    mem_timing_03_modify_timing => "mem_timing/individual/03-modify_timing.gb",
+    dmg_sound_01_registers => "dmg_sound/rom_singles/01-registers.gb",
+    dmg_sound_02_len_ctr => "dmg_sound/rom_singles/02-len ctr.gb",
+    dmg_sound_03_trigger => "dmg_sound/rom_singles/03-trigger.gb",
+    dmg_sound_04_sweep => "dmg_sound/rom_singles/04-sweep.gb",
+    dmg_sound_05_sweep_details => "dmg_sound/rom_singles/05-sweep details.gb",
+    dmg_sound_06_overflow_on_trigger => "dmg_sound/rom_singles/06-overflow on trigger.gb",
+    dmg_sound_07_len_sweep_period_sync => "dmg_sound/rom_singles/07-len sweep period sync.gb",
+    dmg_sound_08_len_ctr_during_power => "dmg_sound/rom_singles/08-len ctr during power.gb",
+    dmg_sound_09_wave_read_while_on => "dmg_sound/rom_singles/09-wave read while on.gb",
+    dmg_sound_10_wave_trigger_while_on => "dmg_sound/rom_singles/10-wave trigger while on.gb",
+    dmg_sound_11_regs_after_power => "dmg_sound/rom_singles/11-regs after power.gb",
+    dmg_sound_12_wave_write_while_on => "dmg_sound/rom_singles/12-wave write while on.gb",
+    halt_bug => "halt_bug.gb",
+}
@@ -0,0 +1,25 @@
+//! Helpers shared by the test ROM integration tests.
+
+use std::{fs, path::PathBuf};
+
+use rustboy::{frontend::NullFrontend, gb, Gameboy};
+
+/// Directory the test ROMs are expected in.
+pub fn roms_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("files/game-boy-test-roms")
+}
+
+/// Load the test ROM at `path` (relative to [`roms_dir`]) into a new [`Gameboy`].
+/// Returns `None` if the ROM doesn't exist, so the test can be skipped.
+pub fn load(path: &str) -> Option<Gameboy> {
+    let path = roms_dir().join(path);
+    let Ok(rom) = fs::read(&path) else {
+        eprintln!("skipping, {} not found", path.display());
+        return None;
+    };
+    Some(Gameboy::new(
+        &rom,
+        Box::new(NullFrontend),
+        gb::Config::default(),
+    ))
+}
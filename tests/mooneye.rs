@@ -0,0 +1,93 @@
+//! Mooneye test suite acceptance ROMs, skipped if the ROMs aren't present under `files/`.
+
+mod common;
+
+use rustboy::harness::{mooneye, Outcome};
+
+fn run(path: &str) {
+    let Some(mut gb) = common::load(&format!("mooneye-test-suite/acceptance/{path}")) else {
+        return;
+    };
+    let report = mooneye::run(&mut gb, mooneye::DEFAULT_TIMEOUT_FRAMES);
+    assert_eq!(report.outcome, Outcome::Passed, "{}", report.output);
+}
+
+macro_rules! mooneye_tests {
+    ($($name:ident => $path:literal,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                run($path);
+            }
+        )*
+    };
+}
+
+mooneye_tests! {
+    add_sp_e_timing => "add_sp_e_timing.gb",
+    boot_div_dmg_abc_mgb => "boot_div-dmgABCmgb.gb",
+    boot_hwio_dmg_abc_mgb => "boot_hwio-dmgABCmgb.gb",
+    boot_regs_dmg_abc => "boot_regs-dmgABC.gb",
+    call_cc_timing => "call_cc_timing.gb",
+    call_cc_timing2 => "call_cc_timing2.gb",
+    call_timing => "call_timing.gb",
+    call_timing2 => "call_timing2.gb",
+    di_timing_gs => "di_timing-GS.gb",
+    div_timing => "div_timing.gb",
+    ei_sequence => "ei_sequence.gb",
+    ei_timing => "ei_timing.gb",
+    halt_ime0_ei => "halt_ime0_ei.gb",
+    halt_ime0_nointr_timing => "halt_ime0_nointr_timing.gb",
+    halt_ime1_timing => "halt_ime1_timing.gb",
+    halt_ime1_timing2_gs => "halt_ime1_timing2-GS.gb",
+    if_ie_registers => "if_ie_registers.gb",
+    intr_timing => "intr_timing.gb",
+    jp_cc_timing => "jp_cc_timing.gb",
+    jp_timing => "jp_timing.gb",
+    ld_hl_sp_e_timing => "ld_hl_sp_e_timing.gb",
+    oam_dma_restart => "oam_dma_restart.gb",
+    oam_dma_start => "oam_dma_start.gb",
+    oam_dma_timing => "oam_dma_timing.gb",
+    pop_timing => "pop_timing.gb",
+    push_timing => "push_timing.gb",
+    rapid_di_ei => "rapid_di_ei.gb",
+    ret_cc_timing => "ret_cc_timing.gb",
+    ret_timing => "ret_timing.gb",
+    reti_intr_timing => "reti_intr_timing.gb",
+    reti_timing => "reti_timing.gb",
+    rst_timing => "rst_timing.gb",
+    bits_mem_oam => "bits/mem_oam.gb",
+    bits_reg_f => "bits/reg_f.gb",
+    bits_unused_hwio_gs => "bits/unused_hwio-GS.gb",
+    instr_daa => "instr/daa.gb",
+    interrupts_ie_push => "interrupts/ie_push.gb",
+    oam_dma_basic => "oam_dma/basic.gb",
+    oam_dma_reg_read => "oam_dma/reg_read.gb",
+    oam_dma_sources_gs => "oam_dma/sources-GS.gb",
+    ppu_hblank_ly_scx_timing_gs => "ppu/hblank_ly_scx_timing-GS.gb",
+    ppu_intr_1_2_timing_gs => "ppu/intr_1_2_timing-GS.gb",
+    ppu_intr_2_0_timing => "ppu/intr_2_0_timing.gb",
+    ppu_intr_2_mode0_timing => "ppu/intr_2_mode0_timing.gb",
+    ppu_intr_2_mode0_timing_sprites => "ppu/intr_2_mode0_timing_sprites.gb",
+    ppu_intr_2_mode3_timing => "ppu/intr_2_mode3_timing.gb",
+    ppu_intr_2_oam_ok_timing => "ppu/intr_2_oam_ok_timing.gb",
+    ppu_lcdon_timing_gs => "ppu/lcdon_timing-GS.gb",
+    ppu_lcdon_write_timing_gs => "ppu/lcdon_write_timing-GS.gb",
+    ppu_stat_irq_blocking => "ppu/stat_irq_blocking.gb",
+    ppu_stat_lyc_onoff => "ppu/stat_lyc_onoff.gb",
+    ppu_vblank_stat_intr_gs => "ppu/vblank_stat_intr-GS.gb",
+    serial_boot_sclk_align_dmg_abc_mgb => "serial/boot_sclk_align-dmgABCmgb.gb",
+    timer_div_write => "timer/div_write.gb",
+    timer_rapid_toggle => "timer/rapid_toggle.gb",
+    timer_tim00 => "timer/tim00.gb",
+    timer_tim00_div_trigger => "timer/tim00_div_trigger.gb",
+    timer_tim01 => "timer/tim01.gb",
+    timer_tim01_div_trigger => "timer/tim01_div_trigger.gb",
+    timer_tim10 => "timer/tim10.gb",
+    timer_tim10_div_trigger => "timer/tim10_div_trigger.gb",
+    timer_tim11 => "timer/tim11.gb",
+    timer_tim11_div_trigger => "timer/tim11_div_trigger.gb",
+    timer_tima_reload => "timer/tima_reload.gb",
+    timer_tima_write_reloading => "timer/tima_write_reloading.gb",
+    timer_tma_write_reloading => "timer/tma_write_reloading.gb",
+}
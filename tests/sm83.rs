@@ -0,0 +1,117 @@
+//! SM83 single step tests (<https://github.com/SingleStepTests/sm83>), skipped if the JSON
+//! files aren't present under `files/sm83/v1`.
+
+use std::{fs, path::PathBuf};
+
+use rustboy::{
+    bus::Access,
+    harness::sm83::{self, State, TestCase},
+};
+use serde_json::Value;
+
+/// Maximum failures reported per file, one broken instruction fails all of its vectors.
+const MAX_FAILURES_PER_FILE: usize = 3;
+
+fn tests_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("files/sm83/v1")
+}
+
+fn field<T: TryFrom<u64>>(value: &Value, name: &str) -> T {
+    value[name]
+        .as_u64()
+        .and_then(|v| T::try_from(v).ok())
+        .unwrap_or_else(|| panic!("invalid field {name} in {value}"))
+}
+
+fn parse_state(value: &Value) -> State {
+    let ram = value["ram"]
+        .as_array()
+        .expect("ram should be an array")
+        .iter()
+        .map(|entry| {
+            let address = entry[0].as_u64().expect("address should be a number");
+            let val = entry[1].as_u64().expect("value should be a number");
+            (address as u16, val as u8)
+        })
+        .collect();
+    State {
+        pc: field(value, "pc"),
+        sp: field(value, "sp"),
+        a: field(value, "a"),
+        f: field(value, "f"),
+        b: field(value, "b"),
+        c: field(value, "c"),
+        d: field(value, "d"),
+        e: field(value, "e"),
+        h: field(value, "h"),
+        l: field(value, "l"),
+        ime: field::<u8>(value, "ime") != 0,
+        ram,
+    }
+}
+
+/// Parse a `[address, value, pins]` cycle. Anything but a read or write, including `null`
+/// entries, is an internal cycle. Only the access is compared, not what's left on the bus.
+fn parse_cycle(value: &Value) -> Access {
+    if value.is_null() {
+        return Access::Idle;
+    }
+    let address = value[0].as_u64().map(|address| address as u16);
+    let val = value[1].as_u64().map(|val| val as u8);
+    match (value[2].as_str(), address, val) {
+        (Some("r-m"), Some(address), Some(val)) => Access::Read(address, val),
+        (Some("-wm"), Some(address), Some(val)) => Access::Write(address, val),
+        _ => Access::Idle,
+    }
+}
+
+fn parse_test(value: &Value) -> TestCase {
+    TestCase {
+        name: value["name"].as_str().unwrap_or_default().to_owned(),
+        initial: parse_state(&value["initial"]),
+        expected: parse_state(&value["final"]),
+        cycles: value["cycles"]
+            .as_array()
+            .expect("cycles should be an array")
+            .iter()
+            .map(parse_cycle)
+            .collect(),
+    }
+}
+
+#[test]
+fn sm83_single_step_tests() {
+    let Ok(entries) = fs::read_dir(tests_dir()) else {
+        eprintln!("skipping, {} not found", tests_dir().display());
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    let mut failures = Vec::new();
+    for path in paths {
+        let json = fs::read_to_string(&path).expect("cannot read test file");
+        let tests: Value = serde_json::from_str(&json).expect("cannot parse test file");
+        let tests = tests.as_array().expect("test file should be an array");
+
+        let errors: Vec<String> = tests
+            .iter()
+            .map(parse_test)
+            .filter_map(|test| sm83::run(&test).err())
+            .collect();
+        if !errors.is_empty() {
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            failures.push(format!("{file}: {}/{} failed", errors.len(), tests.len()));
+            failures.extend(
+                errors
+                    .into_iter()
+                    .take(MAX_FAILURES_PER_FILE)
+                    .map(|error| format!("  {error}")),
+            );
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}